@@ -0,0 +1,59 @@
+//! Compares the interpreter with the compiled dispatch of `Executor::compile`.
+//!
+//! `cargo bench --bench compiled`
+
+use ni::{
+    common::{BinOp, Op, OpType, Operand, UWord, UnOp},
+    executor::{Executor, Function},
+};
+use std::time::{Duration, Instant};
+
+const STEPS: u32 = 10_000_000;
+const SAMPLES: usize = 5;
+
+fn run(functions: &[Function], compile: bool) -> Duration {
+    let mut exe = Executor::new(functions);
+    exe.call(0, 0).unwrap();
+
+    if compile {
+        exe.compile();
+    }
+
+    let start = Instant::now();
+
+    for _ in 0..STEPS {
+        exe.execute().unwrap();
+    }
+
+    start.elapsed()
+}
+
+fn main() {
+    let program = [
+        Op::Set(BinOp::new(Operand::Loc(8), Operand::Loc(0)), OpType::U32),
+        Op::Mul(BinOp::new(Operand::Loc(8), Operand::Val(3)), OpType::U32),
+        Op::Add(BinOp::new(Operand::Loc(4), Operand::Loc(8)), OpType::U32),
+        Op::Inc(UnOp::new(Operand::Loc(0)), OpType::U32),
+        Op::Ifl(
+            BinOp::new(Operand::Loc(0), Operand::Val(UWord::MAX)),
+            OpType::U32,
+        ),
+        Op::Go(Operand::Val(0)),
+    ];
+
+    let functions = [Function::new(16, &program)];
+
+    for (name, compile) in [("interpreted", false), ("compiled", true)] {
+        let best = (0..SAMPLES)
+            .map(|_| run(&functions, compile))
+            .min()
+            .unwrap();
+
+        println!(
+            "{:<12} {:>10.2?} {:>6.2} ns/op",
+            name,
+            best,
+            best.as_nanos() as f64 / STEPS as f64
+        );
+    }
+}
@@ -0,0 +1,29 @@
+//! Values available to a program through the `giv` instruction.
+//!
+//! Every value is written as `uw`.
+
+use super::UWord;
+
+/// Size of the machine word in bytes.
+pub const WORD_SIZE: UWord = 0x00;
+
+/// Stack limit in bytes.
+pub const STACK_LIMIT: UWord = 0x01;
+
+/// Heap limit in bytes.
+pub const HEAP_LIMIT: UWord = 0x02;
+
+/// Current stack length in bytes.
+pub const STACK_LEN: UWord = 0x03;
+
+/// Address of the heap beginning.
+pub const HEAP_BASE: UWord = 0x04;
+
+/// Maximum number of open files.
+pub const FILES_LIMIT: UWord = 0x05;
+
+/// Current call depth.
+pub const CALL_DEPTH: UWord = 0x06;
+
+/// Interpreter version.
+pub const VERSION: UWord = 0x07;
@@ -3,6 +3,7 @@ mod tests;
 
 pub mod bits;
 mod expected;
+pub mod giv_codes;
 pub mod op_codes;
 mod operations;
 
@@ -144,3 +144,45 @@ pub const CPY: u8 = 0x2F;
 
 /// Get interpreter value.
 pub const GIV: u8 = 0x30;
+
+/// Allocate memory.
+pub const ALC: u8 = 0x31;
+
+/// Free memory.
+pub const FRE: u8 = 0x32;
+
+/// Checked addition.
+pub const ADC: u8 = 0x33;
+
+/// Checked subtraction.
+pub const SBC: u8 = 0x34;
+
+/// Checked multiplication.
+pub const MLC: u8 = 0x35;
+
+/// Saturating addition.
+pub const ADS: u8 = 0x36;
+
+/// Saturating subtraction.
+pub const SBS: u8 = 0x37;
+
+/// Saturating multiplication.
+pub const MLS: u8 = 0x38;
+
+/// Spawn context.
+pub const SPW: u8 = 0x39;
+
+/// Join context.
+pub const JON: u8 = 0x3A;
+
+/// Input block.
+pub const INB: u8 = 0x3B;
+
+/// Output block.
+pub const OTB: u8 = 0x3C;
+
+/// Input value.
+pub const INV: u8 = 0x3D;
+
+/// Output value.
+pub const OTV: u8 = 0x3E;
@@ -268,11 +268,28 @@ pub enum Op {
     In(BinOp),
     Out(UnOp),
     Fls,
+    Opn(Operand, Operand, Operand),
+    Cls(Operand),
     Sfd(Operand),
     Gfd(Operand),
     Zer(Operand, Operand),
     Cmp(Operand, Operand, Operand),
     Cpy(Operand, Operand, Operand),
+    Giv(Operand, Operand),
+    Alc(Operand, Operand),
+    Fre(Operand),
+    Adc(BinOp, OpType),
+    Sbc(BinOp, OpType),
+    Mlc(BinOp, OpType),
+    Ads(BinOp, OpType),
+    Sbs(BinOp, OpType),
+    Mls(BinOp, OpType),
+    Spw(Operand, Operand, Operand),
+    Jon(Operand, Operand),
+    Inb(Operand, Operand, Operand),
+    Otb(Operand, Operand, Operand),
+    Inv(BinOp, OpType),
+    Otv(BinOp, OpType),
 }
 
 impl Op {
@@ -332,11 +349,28 @@ impl Op {
             In(..) => IN,
             Out(..) => OUT,
             Fls => FLS,
+            Opn(..) => OPN,
+            Cls(..) => CLS,
             Sfd(..) => SFD,
             Gfd(..) => GFD,
             Zer(..) => ZER,
             Cmp(..) => CMP,
             Cpy(..) => CPY,
+            Giv(..) => GIV,
+            Alc(..) => ALC,
+            Fre(..) => FRE,
+            Adc(..) => ADC,
+            Sbc(..) => SBC,
+            Mlc(..) => MLC,
+            Ads(..) => ADS,
+            Sbs(..) => SBS,
+            Mls(..) => MLS,
+            Spw(..) => SPW,
+            Jon(..) => JON,
+            Inb(..) => INB,
+            Otb(..) => OTB,
+            Inv(..) => INV,
+            Otv(..) => OTV,
         }
     }
 }
@@ -387,11 +421,28 @@ impl std::fmt::Debug for Op {
             In(b) => write!(f, "in  {:?}", b),
             Out(u) => write!(f, "out {:?}", u),
             Fls => write!(f, "fls"),
+            Opn(x, y, z) => write!(f, "opn {:?} {:?} {:?}", x, y, z),
+            Cls(x) => write!(f, "cls {:?}", x),
             Sfd(x) => write!(f, "sfd {:?}", x),
             Gfd(x) => write!(f, "gfd {:?}", x),
             Zer(x, y) => write!(f, "zer {:?} {:?}", x, y),
             Cmp(x, y, z) => write!(f, "cmp {:?} {:?} {:?}", x, y, z),
             Cpy(x, y, z) => write!(f, "cpy {:?} {:?} {:?}", x, y, z),
+            Giv(x, y) => write!(f, "giv {:?} {:?}", x, y),
+            Alc(x, y) => write!(f, "alc {:?} {:?}", x, y),
+            Fre(x) => write!(f, "fre {:?}", x),
+            Adc(b, t) => write!(f, "adc {:?} {:?}", t, b),
+            Sbc(b, t) => write!(f, "sbc {:?} {:?}", t, b),
+            Mlc(b, t) => write!(f, "mlc {:?} {:?}", t, b),
+            Ads(b, t) => write!(f, "ads {:?} {:?}", t, b),
+            Sbs(b, t) => write!(f, "sbs {:?} {:?}", t, b),
+            Mls(b, t) => write!(f, "mls {:?} {:?}", t, b),
+            Spw(x, y, z) => write!(f, "spw {:?} {:?} {:?}", x, y, z),
+            Jon(x, y) => write!(f, "jon {:?} {:?}", x, y),
+            Inb(x, y, z) => write!(f, "inb {:?} {:?} {:?}", x, y, z),
+            Otb(x, y, z) => write!(f, "otb {:?} {:?} {:?}", x, y, z),
+            Inv(b, t) => write!(f, "inv {:?} {:?}", t, b),
+            Otv(b, t) => write!(f, "otv {:?} {:?}", t, b),
         }
     }
 }
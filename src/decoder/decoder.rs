@@ -217,6 +217,13 @@ where
             Out(un_op)
         }
         FLS => Fls,
+        OPN => {
+            let x = decode(bytes)?;
+            let y = decode(bytes)?;
+            let z = decode(bytes)?;
+            Opn(x, y, z)
+        }
+        CLS => Cls(decode(bytes)?),
         SFD => Sfd(decode(bytes)?),
         GFD => Gfd(decode(bytes)?),
         ZER => {
@@ -236,6 +243,72 @@ where
             let z = decode(bytes)?;
             Cpy(x, y, z)
         }
+        GIV => {
+            let x = decode(bytes)?;
+            let y = decode(bytes)?;
+            Giv(x, y)
+        }
+        ALC => {
+            let x = decode(bytes)?;
+            let y = decode(bytes)?;
+            Alc(x, y)
+        }
+        FRE => Fre(decode(bytes)?),
+        ADC => {
+            let (bin_op, op_type) = decode(bytes)?;
+            Adc(bin_op, op_type)
+        }
+        SBC => {
+            let (bin_op, op_type) = decode(bytes)?;
+            Sbc(bin_op, op_type)
+        }
+        MLC => {
+            let (bin_op, op_type) = decode(bytes)?;
+            Mlc(bin_op, op_type)
+        }
+        ADS => {
+            let (bin_op, op_type) = decode(bytes)?;
+            Ads(bin_op, op_type)
+        }
+        SBS => {
+            let (bin_op, op_type) = decode(bytes)?;
+            Sbs(bin_op, op_type)
+        }
+        MLS => {
+            let (bin_op, op_type) = decode(bytes)?;
+            Mls(bin_op, op_type)
+        }
+        SPW => {
+            let x = decode(bytes)?;
+            let y = decode(bytes)?;
+            let z = decode(bytes)?;
+            Spw(x, y, z)
+        }
+        JON => {
+            let x = decode(bytes)?;
+            let y = decode(bytes)?;
+            Jon(x, y)
+        }
+        INB => {
+            let x = decode(bytes)?;
+            let y = decode(bytes)?;
+            let z = decode(bytes)?;
+            Inb(x, y, z)
+        }
+        OTB => {
+            let x = decode(bytes)?;
+            let y = decode(bytes)?;
+            let z = decode(bytes)?;
+            Otb(x, y, z)
+        }
+        INV => {
+            let (bin_op, op_type) = decode(bytes)?;
+            Inv(bin_op, op_type)
+        }
+        OTV => {
+            let (bin_op, op_type) = decode(bytes)?;
+            Otv(bin_op, op_type)
+        }
         _ => return Err(DecodeError::UnknownOpCode),
     };
 
@@ -823,6 +896,43 @@ mod tests {
         assert!(code.is_empty());
     }
 
+    #[test]
+    fn decode_opn() {
+        let code = [
+            // opn loc(0) ref(1) val(4)
+            OPN,
+            0,
+            0b1100_0000,
+            1,
+            0b1011_0000,
+            4,
+        ];
+
+        let expected = Op::Opn(Operand::Loc(0), Operand::Ref(1), Operand::Val(4));
+
+        let mut code = code.as_ref();
+        let actual = decode_op(&mut code).unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(code.is_empty());
+    }
+
+    #[test]
+    fn decode_cls() {
+        let code = [
+            // cls loc(0)
+            CLS, 0,
+        ];
+
+        let expected = Op::Cls(Operand::Loc(0));
+
+        let mut code = code.as_ref();
+        let actual = decode_op(&mut code).unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(code.is_empty());
+    }
+
     #[test]
     fn decode_cpy() {
         let code = [
@@ -842,4 +952,113 @@ mod tests {
         assert_eq!(actual, expected);
         assert!(code.is_empty());
     }
+
+    #[test]
+    fn decode_giv() {
+        let code = [
+            // giv loc(0) val(4)
+            GIV,
+            0,
+            0b1011_0000,
+            4,
+        ];
+
+        let expected = Op::Giv(Operand::Loc(0), Operand::Val(4));
+
+        let mut code = code.as_ref();
+        let actual = decode_op(&mut code).unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(code.is_empty());
+    }
+
+    #[test]
+    fn decode_alc() {
+        let code = [
+            // alc loc(0) val(16)
+            ALC,
+            0,
+            0b1011_0000,
+            16,
+        ];
+
+        let expected = Op::Alc(Operand::Loc(0), Operand::Val(16));
+
+        let mut code = code.as_ref();
+        let actual = decode_op(&mut code).unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(code.is_empty());
+    }
+
+    #[test]
+    fn decode_fre() {
+        let code = [
+            // fre loc(0)
+            FRE, 0,
+        ];
+
+        let expected = Op::Fre(Operand::Loc(0));
+
+        let mut code = code.as_ref();
+        let actual = decode_op(&mut code).unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(code.is_empty());
+    }
+
+    #[test]
+    fn decode_mls() {
+        let code = [
+            // mls i16 loc(8) loc(16)
+            MLS,
+            0b0000_0011,
+            8,
+            16,
+        ];
+
+        let expected = Op::Mls(BinOp::new(Operand::Loc(8), Operand::Loc(16)), OpType::I16);
+
+        let mut code = code.as_ref();
+        let actual = decode_op(&mut code).unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(code.is_empty());
+    }
+
+    #[test]
+    fn decode_jon() {
+        let code = [
+            // jon loc(0) loc(4)
+            JON, 0, 4,
+        ];
+
+        let expected = Op::Jon(Operand::Loc(0), Operand::Loc(4));
+
+        let mut code = code.as_ref();
+        let actual = decode_op(&mut code).unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(code.is_empty());
+    }
+
+    #[test]
+    fn decode_otb() {
+        let code = [
+            // otb loc(0) loc(4) val(8)
+            OTB,
+            0,
+            4,
+            0b1011_0000,
+            8,
+        ];
+
+        let expected = Op::Otb(Operand::Loc(0), Operand::Loc(4), Operand::Val(8));
+
+        let mut code = code.as_ref();
+        let actual = decode_op(&mut code).unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(code.is_empty());
+    }
 }
@@ -190,6 +190,16 @@ where
             (u, OpType::U8).encode(buf)
         }
         Fls => FLS.encode(buf),
+        Opn(x, y, z) => {
+            OPN.encode(buf)?;
+            x.encode(buf)?;
+            y.encode(buf)?;
+            z.encode(buf)
+        }
+        Cls(x) => {
+            CLS.encode(buf)?;
+            x.encode(buf)
+        }
         Sfd(x) => {
             SFD.encode(buf)?;
             x.encode(buf)
@@ -215,6 +225,75 @@ where
             y.encode(buf)?;
             z.encode(buf)
         }
+        Giv(x, y) => {
+            GIV.encode(buf)?;
+            x.encode(buf)?;
+            y.encode(buf)
+        }
+        Alc(x, y) => {
+            ALC.encode(buf)?;
+            x.encode(buf)?;
+            y.encode(buf)
+        }
+        Fre(x) => {
+            FRE.encode(buf)?;
+            x.encode(buf)
+        }
+        Adc(b, t) => {
+            ADC.encode(buf)?;
+            (b, t).encode(buf)
+        }
+        Sbc(b, t) => {
+            SBC.encode(buf)?;
+            (b, t).encode(buf)
+        }
+        Mlc(b, t) => {
+            MLC.encode(buf)?;
+            (b, t).encode(buf)
+        }
+        Ads(b, t) => {
+            ADS.encode(buf)?;
+            (b, t).encode(buf)
+        }
+        Sbs(b, t) => {
+            SBS.encode(buf)?;
+            (b, t).encode(buf)
+        }
+        Mls(b, t) => {
+            MLS.encode(buf)?;
+            (b, t).encode(buf)
+        }
+        Spw(x, y, z) => {
+            SPW.encode(buf)?;
+            x.encode(buf)?;
+            y.encode(buf)?;
+            z.encode(buf)
+        }
+        Jon(x, y) => {
+            JON.encode(buf)?;
+            x.encode(buf)?;
+            y.encode(buf)
+        }
+        Inb(x, y, z) => {
+            INB.encode(buf)?;
+            x.encode(buf)?;
+            y.encode(buf)?;
+            z.encode(buf)
+        }
+        Otb(x, y, z) => {
+            OTB.encode(buf)?;
+            x.encode(buf)?;
+            y.encode(buf)?;
+            z.encode(buf)
+        }
+        Inv(b, t) => {
+            INV.encode(buf)?;
+            (b, t).encode(buf)
+        }
+        Otv(b, t) => {
+            OTV.encode(buf)?;
+            (b, t).encode(buf)
+        }
     }
 }
 
@@ -639,6 +718,16 @@ mod tests {
         assert_eq!(buf, &[FLS]);
     }
 
+    #[test]
+    fn encode_opn() {
+        let op = Op::Opn(Operand::Loc(0), Operand::Ref(1), Operand::Val(4));
+
+        let mut buf = vec![];
+        encode_op(op, &mut buf).unwrap();
+
+        assert_eq!(buf, &[OPN, 0, 0b1100_0000, 1, 0b1011_0000, 4]);
+    }
+
     #[test]
     fn encode_cpy() {
         let op = Op::Cpy(Operand::Loc(0), Operand::Loc(1), Operand::Val(12));
@@ -648,4 +737,54 @@ mod tests {
 
         assert_eq!(buf, &[CPY, 0, 1, 0b1011_0000, 12]);
     }
+
+    #[test]
+    fn encode_giv() {
+        let op = Op::Giv(Operand::Loc(0), Operand::Val(giv_codes::HEAP_BASE));
+
+        let mut buf = vec![];
+        encode_op(op, &mut buf).unwrap();
+
+        assert_eq!(buf, &[GIV, 0, 0b1011_0000, 4]);
+    }
+
+    #[test]
+    fn encode_alc() {
+        let op = Op::Alc(Operand::Loc(0), Operand::Val(16));
+
+        let mut buf = vec![];
+        encode_op(op, &mut buf).unwrap();
+
+        assert_eq!(buf, &[ALC, 0, 0b1011_0000, 16]);
+    }
+
+    #[test]
+    fn encode_adc() {
+        let op = Op::Adc(BinOp::new(Operand::Loc(8), Operand::Loc(16)), OpType::I16);
+
+        let mut buf = vec![];
+        encode_op(op, &mut buf).unwrap();
+
+        assert_eq!(buf, &[ADC, 0b0000_0011, 8, 16]);
+    }
+
+    #[test]
+    fn encode_spw() {
+        let op = Op::Spw(Operand::Loc(0), Operand::Val(1), Operand::Loc(4));
+
+        let mut buf = vec![];
+        encode_op(op, &mut buf).unwrap();
+
+        assert_eq!(buf, &[SPW, 0, 0b1011_0000, 1, 4]);
+    }
+
+    #[test]
+    fn encode_inv() {
+        let op = Op::Inv(BinOp::new(Operand::Loc(8), Operand::Loc(16)), OpType::U32);
+
+        let mut buf = vec![];
+        encode_op(op, &mut buf).unwrap();
+
+        assert_eq!(buf, &[INV, 0b0000_0100, 8, 16]);
+    }
 }
@@ -0,0 +1,270 @@
+use super::{
+    memory::{MemoryError, MemoryPage},
+    snapshot::{Reader, SnapshotError, Writer},
+};
+use crate::common::UWord;
+use std::collections::BTreeMap;
+
+const ALIGN: UWord = std::mem::size_of::<UWord>() as UWord;
+
+/// First-fit allocator over a memory page.
+///
+/// All offsets are relative to the beginning of the page.
+#[derive(Debug, Default)]
+pub struct Allocator {
+    used: BTreeMap<UWord, UWord>,
+    free: BTreeMap<UWord, UWord>,
+}
+
+impl Allocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn align(size: UWord) -> Option<UWord> {
+        let size = size.max(1);
+        let rem = size % ALIGN;
+
+        if rem == 0 {
+            Some(size)
+        } else {
+            size.checked_add(ALIGN - rem)
+        }
+    }
+
+    pub fn alloc(&mut self, page: &mut MemoryPage, size: UWord) -> Result<UWord, MemoryError> {
+        let size = Self::align(size).ok_or(MemoryError::PageOverflow(page.name()))?;
+
+        let fit = self
+            .free
+            .iter()
+            .find(|(_, &block)| block >= size)
+            .map(|(&ptr, &block)| (ptr, block));
+
+        let ptr = match fit {
+            Some((ptr, block)) => {
+                self.free.remove(&ptr);
+
+                if block > size {
+                    self.free.insert(ptr + size, block - size);
+                }
+
+                ptr
+            }
+            None => {
+                // The last free block can be extended up to the required size
+                let last = self
+                    .free
+                    .iter()
+                    .next_back()
+                    .map(|(&ptr, &block)| (ptr, block))
+                    .filter(|&(ptr, block)| ptr + block == page.len());
+
+                match last {
+                    Some((ptr, block)) => {
+                        page.expand(size - block)?;
+                        self.free.remove(&ptr);
+                        ptr
+                    }
+                    None => {
+                        let ptr = page.len();
+                        page.expand(size)?;
+                        ptr
+                    }
+                }
+            }
+        };
+
+        self.used.insert(ptr, size);
+        Ok(ptr)
+    }
+
+    pub fn free(&mut self, page: &mut MemoryPage, ptr: UWord) -> Result<(), MemoryError> {
+        let mut size = match self.used.remove(&ptr) {
+            Some(size) => size,
+            None if self.is_free(ptr) => return Err(MemoryError::DoubleFree(ptr)),
+            None => return Err(MemoryError::InvalidFree(ptr)),
+        };
+
+        let mut start = ptr;
+
+        // Merge with the next free block
+        if let Some(next) = self.free.remove(&(ptr + size)) {
+            size += next;
+        }
+
+        // Merge with the previous free block
+        let prev = self
+            .free
+            .range(..ptr)
+            .next_back()
+            .map(|(&ptr, &block)| (ptr, block))
+            .filter(|&(prev, block)| prev + block == ptr);
+
+        if let Some((prev, block)) = prev {
+            self.free.remove(&prev);
+            start = prev;
+            size += block;
+        }
+
+        // The block at the end of the page is given back to the page
+        if start + size == page.len() {
+            page.narrow(size)?;
+        } else {
+            self.free.insert(start, size);
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, w: &mut Writer) {
+        for blocks in [&self.used, &self.free] {
+            w.word(blocks.len() as UWord);
+
+            for (&ptr, &size) in blocks {
+                w.word(ptr);
+                w.word(size);
+            }
+        }
+    }
+
+    /// Loads blocks of a page of `len` bytes. The blocks must be inside the page
+    /// and must not overlap.
+    pub fn load(r: &mut Reader, len: UWord) -> Result<Self, SnapshotError> {
+        let mut alloc = Self::new();
+
+        for blocks in [&mut alloc.used, &mut alloc.free] {
+            for _ in 0..r.word()? {
+                let ptr = r.word()?;
+                let size = r.word()?;
+
+                match ptr.checked_add(size) {
+                    Some(end) if size != 0 && end <= len => (),
+                    _ => return Err(SnapshotError::InvalidData),
+                }
+
+                if blocks.insert(ptr, size).is_some() {
+                    return Err(SnapshotError::InvalidData);
+                }
+            }
+        }
+
+        let mut blocks: Vec<_> = alloc.used.iter().chain(&alloc.free).collect();
+        blocks.sort_unstable();
+
+        if blocks.windows(2).any(|w| w[0].0 + w[0].1 > *w[1].0) {
+            return Err(SnapshotError::InvalidData);
+        }
+
+        Ok(alloc)
+    }
+
+    /// Returns offsets and sizes of the used blocks.
+    pub fn used(&self) -> impl Iterator<Item = (UWord, UWord)> + '_ {
+        self.used.iter().map(|(&ptr, &size)| (ptr, size))
+    }
+
+    fn is_free(&self, ptr: UWord) -> bool {
+        self.free
+            .range(..=ptr)
+            .next_back()
+            .is_some_and(|(&start, &block)| ptr < start + block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocator_alloc_free() {
+        let mut page = MemoryPage::new(2048, "heap");
+        let mut alloc = Allocator::new();
+
+        let a = alloc.alloc(&mut page, 3).unwrap();
+        let b = alloc.alloc(&mut page, ALIGN * 2).unwrap();
+        let c = alloc.alloc(&mut page, 1).unwrap();
+        assert_eq!((a, b, c), (0, ALIGN, ALIGN * 3));
+        assert_eq!(page.len(), ALIGN * 4);
+
+        alloc.free(&mut page, b).unwrap();
+        assert_eq!(page.len(), ALIGN * 4);
+
+        // Reuses the freed block
+        assert_eq!(alloc.alloc(&mut page, ALIGN), Ok(ALIGN));
+        assert_eq!(alloc.alloc(&mut page, ALIGN), Ok(ALIGN * 2));
+
+        // Tail blocks are given back to the page
+        alloc.free(&mut page, c).unwrap();
+        assert_eq!(page.len(), ALIGN * 3);
+    }
+
+    #[test]
+    fn allocator_merge() {
+        let mut page = MemoryPage::new(2048, "heap");
+        let mut alloc = Allocator::new();
+
+        let a = alloc.alloc(&mut page, ALIGN).unwrap();
+        let b = alloc.alloc(&mut page, ALIGN).unwrap();
+        let c = alloc.alloc(&mut page, ALIGN).unwrap();
+        let _ = alloc.alloc(&mut page, ALIGN).unwrap();
+
+        alloc.free(&mut page, a).unwrap();
+        alloc.free(&mut page, c).unwrap();
+        alloc.free(&mut page, b).unwrap();
+
+        assert_eq!(alloc.alloc(&mut page, ALIGN * 3), Ok(0));
+        assert_eq!(page.len(), ALIGN * 4);
+    }
+
+    #[test]
+    fn allocator_load() {
+        let load = |blocks: &[(UWord, UWord)], free: &[(UWord, UWord)]| {
+            let mut w = Writer::new();
+
+            for blocks in [blocks, free] {
+                w.word(blocks.len() as UWord);
+
+                for &(ptr, size) in blocks {
+                    w.word(ptr);
+                    w.word(size);
+                }
+            }
+
+            let buf = w.finish();
+            Allocator::load(&mut Reader::new(&buf).unwrap(), 64).map(|_| ())
+        };
+
+        assert_eq!(load(&[(0, 16), (16, 8)], &[(24, 40)]), Ok(()));
+        assert_eq!(
+            load(&[(0, 16)], &[(60, 8)]),
+            Err(SnapshotError::InvalidData)
+        );
+        assert_eq!(load(&[(0, 16)], &[(8, 8)]), Err(SnapshotError::InvalidData));
+        assert_eq!(load(&[(8, 0)], &[]), Err(SnapshotError::InvalidData));
+        assert_eq!(
+            load(&[(UWord::MAX, 2)], &[]),
+            Err(SnapshotError::InvalidData)
+        );
+    }
+
+    #[test]
+    fn allocator_errors() {
+        let mut page = MemoryPage::new(2048, "heap");
+        let mut alloc = Allocator::new();
+
+        let a = alloc.alloc(&mut page, ALIGN).unwrap();
+        let _ = alloc.alloc(&mut page, ALIGN).unwrap();
+
+        assert_eq!(
+            alloc.free(&mut page, a + 1),
+            Err(MemoryError::InvalidFree(a + 1))
+        );
+        alloc.free(&mut page, a).unwrap();
+        assert_eq!(alloc.free(&mut page, a), Err(MemoryError::DoubleFree(a)));
+        assert_eq!(
+            alloc.alloc(&mut page, 4096),
+            Err(MemoryError::PageOverflow("heap"))
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use crate::common::UWord;
+use std::any::Any;
+
+/// Device mapped to a range of the address space.
+///
+/// Reads and writes of the range are passed to the device with the offset from
+/// the beginning of the range. An access never crosses the end of the range.
+pub trait Device: std::fmt::Debug + Send {
+    /// Size of the mapped range.
+    fn size(&self) -> UWord;
+
+    /// Fills `buf` with bytes at the `offset`. Devices that change on reads
+    /// need interior mutability.
+    fn read(&self, offset: UWord, buf: &mut [u8]);
+
+    fn write(&mut self, offset: UWord, bytes: &[u8]);
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Plain memory, like a framebuffer the host reads after the run.
+impl Device for Vec<u8> {
+    fn size(&self) -> UWord {
+        self.len() as UWord
+    }
+
+    fn read(&self, offset: UWord, buf: &mut [u8]) {
+        let offset = offset as usize;
+        buf.copy_from_slice(&self[offset..offset + buf.len()]);
+    }
+
+    fn write(&mut self, offset: UWord, bytes: &[u8]) {
+        let offset = offset as usize;
+        self[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
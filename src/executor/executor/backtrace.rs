@@ -0,0 +1,64 @@
+use super::{ExecutionError, Executor, Location};
+use crate::common::{Op, UWord};
+use std::fmt;
+
+/// Call stack at the point of a failed operation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Backtrace {
+    pub error: ExecutionError,
+
+    /// The operation that failed, if it could be fetched.
+    pub op: Option<Op>,
+
+    /// Frames from the innermost one. Outer frames point at their `clf` operation.
+    pub frames: Vec<Location>,
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.error)?;
+
+        if let Some(op) = &self.op {
+            write!(f, " at `{:?}`", op)?;
+        }
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            write!(
+                f,
+                "\n{:>4}: function {} at {}",
+                i, frame.function_id, frame.program_counter
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Executor {
+    /// Returns the backtrace of the last error returned by `execute`.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+
+    pub(super) fn capture_backtrace(&mut self, error: ExecutionError, program_counter: UWord) {
+        let calls = &self.call_stack[..self.call_depth()];
+        let mut frames = Vec::with_capacity(calls.len());
+        let mut pc = program_counter;
+
+        for call in calls.iter().rev() {
+            frames.push(Location {
+                function_id: call.function_id,
+                program_counter: pc,
+            });
+
+            pc = call.ret_program_counter.wrapping_sub(1);
+        }
+
+        let op = calls
+            .last()
+            .and_then(|call| self.call_program(call).get(program_counter as usize))
+            .copied();
+
+        self.backtrace = Some(Backtrace { error, op, frames });
+    }
+}
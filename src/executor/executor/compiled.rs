@@ -0,0 +1,359 @@
+use super::{Arithmetic, Executed, ExecutionSuccess, Executor};
+use crate::{
+    common::{BinOp, IWord, Op, OpType, Operand, UWord, UnOp},
+    executor::primary::Primary,
+};
+
+/// Operation with resolved operand type. Operands are decoded in `Args`, so the
+/// handler doesn't read the operation again.
+pub(super) type Handler = fn(&mut Executor, &Args) -> Executed;
+
+/// Resolves a handler for every type of the operation.
+macro_rules! typed {
+    ($ot:expr, |$exe:ident, $args:ident, $t:ident| $body:expr) => {
+        match $ot {
+            OpType::F32 => {
+                type $t = f32;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::F64 => {
+                type $t = f64;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            ot => integer!(ot, |$exe, $args, $t| $body),
+        }
+    };
+}
+
+/// Resolves a handler for every integer type of the operation.
+/// Float types are left to the interpreter.
+macro_rules! integer {
+    ($ot:expr, |$exe:ident, $args:ident, $t:ident| $body:expr) => {
+        match $ot {
+            OpType::U8 => {
+                type $t = u8;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::I8 => {
+                type $t = i8;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::U16 => {
+                type $t = u16;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::I16 => {
+                type $t = i16;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::U32 => {
+                type $t = u32;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::I32 => {
+                type $t = i32;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::U64 => {
+                type $t = u64;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::I64 => {
+                type $t = i64;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::Uw => {
+                type $t = UWord;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::Iw => {
+                type $t = IWord;
+                (|$exe: &mut Executor, $args: &Args| $body) as Handler
+            }
+            OpType::F32 | OpType::F64 => interpret,
+        }
+    };
+}
+
+/// Decoded operands of an operation. Operands of unary operations and pairs of
+/// operands without offsets are kept in `bin` too.
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Args {
+    bin: BinOp,
+    arithmetic: Arithmetic,
+}
+
+impl Args {
+    fn of(op: Op) -> Self {
+        use Op::*;
+
+        let bin = match op {
+            Set(b, _)
+            | Add(b, _)
+            | Sub(b, _)
+            | Mul(b, _)
+            | Div(b, _)
+            | Mod(b, _)
+            | And(b, _)
+            | Or(b, _)
+            | Xor(b, _)
+            | Ife(b, _)
+            | Ifl(b, _)
+            | Ifg(b, _)
+            | Ine(b, _)
+            | Inl(b, _)
+            | Ing(b, _)
+            | Ifa(b, _)
+            | Ifo(b, _)
+            | Ifx(b, _)
+            | Ina(b, _)
+            | Ino(b, _)
+            | Inx(b, _)
+            | Adc(b, _)
+            | Sbc(b, _)
+            | Mlc(b, _)
+            | Ads(b, _)
+            | Sbs(b, _)
+            | Mls(b, _) => b,
+            Not(u, _) | Neg(u, _) | Inc(u, _) | Dec(u, _) | Ift(u, _) | Iff(u, _) | Par(u, _) => {
+                match u {
+                    UnOp::None { x } => BinOp::new(x, Operand::Emp),
+                    UnOp::First { x, offset } => BinOp::new(x, Operand::Emp).with_first(offset),
+                }
+            }
+            Shl(x, y, _) | Shr(x, y, _) => BinOp::new(x, y),
+            Go(x) => BinOp::new(x, Operand::Emp),
+            _ => BinOp::new(Operand::Emp, Operand::Emp),
+        };
+
+        Self {
+            bin,
+            arithmetic: Arithmetic::of(op),
+        }
+    }
+
+    fn un(&self) -> UnOp {
+        match self.bin {
+            BinOp::First { x, offset, .. } => UnOp::First { x, offset },
+            BinOp::None { x, .. } | BinOp::Second { x, .. } | BinOp::Both { x, .. } => UnOp::new(x),
+        }
+    }
+
+    fn x(&self) -> Operand {
+        match self.bin {
+            BinOp::None { x, .. }
+            | BinOp::First { x, .. }
+            | BinOp::Second { x, .. }
+            | BinOp::Both { x, .. } => x,
+        }
+    }
+
+    fn y(&self) -> Operand {
+        match self.bin {
+            BinOp::None { y, .. }
+            | BinOp::First { y, .. }
+            | BinOp::Second { y, .. }
+            | BinOp::Both { y, .. } => y,
+        }
+    }
+}
+
+/// Operation compiled into a handler and its operands.
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Compiled {
+    handler: Handler,
+    args: Args,
+}
+
+impl Compiled {
+    /// Calls the handler. The operation is copied out of the compiled functions first,
+    /// so the handler can borrow the executor.
+    #[inline]
+    pub(super) fn execute(self, exe: &mut Executor) -> Executed {
+        (self.handler)(exe, &self.args)
+    }
+}
+
+/// Handler of operations which are not compiled.
+fn interpret(exe: &mut Executor, _: &Args) -> Executed {
+    let &op = exe.current_op()?;
+    exe.interpret(op)
+}
+
+fn compile_op(op: Op) -> Handler {
+    use Op::*;
+
+    match op {
+        Set(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_set::<T>(args.bin)?;
+            exe.next()
+        }),
+        Add(_, t) | Adc(_, t) | Ads(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_add::<T>(args.bin, args.arithmetic)?;
+            exe.next()
+        }),
+        Sub(_, t) | Sbc(_, t) | Sbs(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_sub::<T>(args.bin, args.arithmetic)?;
+            exe.next()
+        }),
+        Mul(_, t) | Mlc(_, t) | Mls(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_mul::<T>(args.bin, args.arithmetic)?;
+            exe.next()
+        }),
+        Div(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_div::<T>(args.bin)?;
+            exe.next()
+        }),
+        Mod(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_mod::<T>(args.bin)?;
+            exe.next()
+        }),
+        Shl(_, _, t) => integer!(t, |exe, args, T| {
+            exe.exec_shl::<T>(args.x(), args.y())?;
+            exe.next()
+        }),
+        Shr(_, _, t) => integer!(t, |exe, args, T| {
+            exe.exec_shr::<T>(args.x(), args.y())?;
+            exe.next()
+        }),
+        And(_, t) => integer!(t, |exe, args, T| {
+            exe.exec_and::<T>(args.bin)?;
+            exe.next()
+        }),
+        Or(_, t) => integer!(t, |exe, args, T| {
+            exe.exec_or::<T>(args.bin)?;
+            exe.next()
+        }),
+        Xor(_, t) => integer!(t, |exe, args, T| {
+            exe.exec_xor::<T>(args.bin)?;
+            exe.next()
+        }),
+        Not(_, t) => integer!(t, |exe, args, T| {
+            exe.exec_not::<T>(args.un())?;
+            exe.next()
+        }),
+        Neg(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_neg::<T>(args.un())?;
+            exe.next()
+        }),
+        Inc(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_inc::<T>(args.un())?;
+            exe.next()
+        }),
+        Dec(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_dec::<T>(args.un())?;
+            exe.next()
+        }),
+        Ift(_, t) => typed!(t, |exe, args, T| {
+            let res = exe.get_un::<T>(args.un())? != T::zero();
+            exe.condition(res)
+        }),
+        Iff(_, t) => typed!(t, |exe, args, T| {
+            let res = exe.get_un::<T>(args.un())? == T::zero();
+            exe.condition(res)
+        }),
+        Ife(_, t) => typed!(t, |exe, args, T| {
+            let res = exe.exec_ife::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Ifl(_, t) => typed!(t, |exe, args, T| {
+            let res = exe.exec_ifl::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Ifg(_, t) => typed!(t, |exe, args, T| {
+            let res = exe.exec_ifg::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Ine(_, t) => typed!(t, |exe, args, T| {
+            let res = exe.exec_ine::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Inl(_, t) => typed!(t, |exe, args, T| {
+            let res = exe.exec_inl::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Ing(_, t) => typed!(t, |exe, args, T| {
+            let res = exe.exec_ing::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Ifa(_, t) => integer!(t, |exe, args, T| {
+            let res = exe.exec_ifa::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Ifo(_, t) => integer!(t, |exe, args, T| {
+            let res = exe.exec_ifo::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Ifx(_, t) => integer!(t, |exe, args, T| {
+            let res = exe.exec_ifx::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Ina(_, t) => integer!(t, |exe, args, T| {
+            let res = exe.exec_ina::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Ino(_, t) => integer!(t, |exe, args, T| {
+            let res = exe.exec_ino::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Inx(_, t) => integer!(t, |exe, args, T| {
+            let res = exe.exec_inx::<T>(args.bin)?;
+            exe.condition(res)
+        }),
+        Par(_, t) => typed!(t, |exe, args, T| {
+            exe.exec_par::<T>(args.un())?;
+            exe.next()
+        }),
+        Nop => |exe, _| exe.next(),
+        Go(_) => |exe, args| {
+            exe.program_counter = exe.get_val(args.x())?;
+            Ok(ExecutionSuccess::Ok)
+        },
+        _ => interpret,
+    }
+}
+
+impl Executor {
+    /// Resolves operations of all functions into handlers of their operand types and
+    /// decodes their operands, so `execute` doesn't dispatch on the operation at every step.
+    pub fn compile(&mut self) {
+        let compiled = (0..self.program.len() as UWord)
+            .map(|id| {
+                self.program
+                    .ops(id)
+                    .iter()
+                    .map(|&op| Compiled {
+                        handler: compile_op(op),
+                        args: Args::of(op),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        self.compiled = Some(compiled);
+    }
+
+    pub fn is_compiled(&self) -> bool {
+        self.compiled.is_some()
+    }
+
+    /// Returns the compiled operation at the program counter.
+    pub(super) fn compiled_op(&self, function_id: UWord) -> Option<&Compiled> {
+        let ops: &Vec<Compiled> = self.compiled.as_ref()?.get(function_id as usize)?;
+        ops.get(self.program_counter as usize)
+    }
+
+    fn next(&mut self) -> Executed {
+        self.program_counter = self.program_counter.wrapping_add(1);
+        Ok(ExecutionSuccess::Ok)
+    }
+
+    fn condition(&mut self, res: bool) -> Executed {
+        if res {
+            self.next()
+        } else {
+            self.pass_condition()?;
+            Ok(ExecutionSuccess::Ok)
+        }
+    }
+}
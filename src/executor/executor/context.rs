@@ -0,0 +1,348 @@
+use super::{Callee, Executed, ExecutionError, ExecutionSuccess, Executor, FunctionCall};
+use crate::{
+    common::{Operand, UWord},
+    executor::{memory::MemoryPage, primary::Primary},
+};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, VecDeque},
+};
+
+/// Suspended execution context.
+#[derive(Debug)]
+pub(super) struct Context {
+    pub(super) id: UWord,
+    pub(super) stack: MemoryPage,
+    pub(super) program_counter: UWord,
+    pub(super) call_stack: Vec<FunctionCall>,
+    pub(super) prepared_call: bool,
+    pub(super) parameter_ptr: UWord,
+}
+
+/// The way the current context is suspended.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(super) enum Suspend {
+    /// Yields to the next ready context.
+    Ready,
+    /// Sleeps for the number of ticks.
+    Sleep(UWord),
+    /// Waits until the context finishes.
+    Join(UWord),
+    /// Finishes with the exit value.
+    Finish(UWord),
+}
+
+/// Execution contexts of an executor. The state of the current context
+/// is kept in the executor itself.
+#[derive(Debug)]
+pub(super) struct Contexts {
+    pub(super) current: UWord,
+    pub(super) next_id: UWord,
+    pub(super) ready: VecDeque<Context>,
+    /// Sleeping contexts with their wake times.
+    pub(super) sleeping: Vec<(u64, Context)>,
+    /// Waiting contexts with the ids of the contexts they join.
+    pub(super) joining: Vec<(UWord, Context)>,
+    pub(super) finished: HashMap<UWord, UWord>,
+    /// Time in ticks. It passes only when the executor stops with `StopReason::Sleep`.
+    pub(super) time: u64,
+    pub(super) limit: Option<usize>,
+    /// Number of contexts that blocked one after another, with the instruction
+    /// count of the last block.
+    blocked: (usize, u64),
+}
+
+impl Contexts {
+    pub(super) fn new(limit: Option<usize>) -> Self {
+        Self {
+            current: 0,
+            next_id: 1,
+            ready: VecDeque::new(),
+            sleeping: Vec::new(),
+            joining: Vec::new(),
+            finished: HashMap::new(),
+            time: 0,
+            limit,
+            blocked: (0, 0),
+        }
+    }
+
+    /// Number of contexts which are not finished, except the current one.
+    pub(super) fn suspended(&self) -> usize {
+        self.ready.len() + self.sleeping.len() + self.joining.len()
+    }
+
+    fn contains(&self, id: UWord) -> bool {
+        self.current == id
+            || self.ready.iter().any(|c| c.id == id)
+            || self.sleeping.iter().any(|(_, c)| c.id == id)
+            || self.joining.iter().any(|(_, c)| c.id == id)
+    }
+
+    /// Advances the time to the earliest wake time and moves the contexts which
+    /// are due to the front of the ready queue. Returns the number of ticks passed.
+    fn wake_sleeping(&mut self) -> u64 {
+        let now = match self.sleeping.iter().map(|&(wake, _)| wake).min() {
+            Some(wake) => wake.max(self.time),
+            None => return 0,
+        };
+
+        let mut due = Vec::new();
+        let mut i = 0;
+
+        while i < self.sleeping.len() {
+            if self.sleeping[i].0 <= now {
+                due.push(self.sleeping.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+
+        // The stable sort keeps contexts with the same wake time in the order they slept
+        due.sort_by_key(|&(wake, _)| wake);
+
+        for (_, context) in due.into_iter().rev() {
+            self.ready.push_front(context);
+        }
+
+        let ticks = now - self.time;
+        self.time = now;
+        ticks
+    }
+}
+
+impl Executor {
+    /// Spawns a context that runs the function with its own call stack and stack page.
+    /// The heap and files are shared between contexts. The `argument` is written
+    /// to the start of the function frame, if the frame has room for it.
+    ///
+    /// Returns the id of the context. The executor starts in the context 0.
+    pub fn spawn(&mut self, function_id: UWord, argument: UWord) -> Result<UWord, ExecutionError> {
+        let frame_size = match self.callee(function_id)? {
+            Callee::Function(frame_size) => frame_size,
+            Callee::Host(_) => return Err(ExecutionError::UnknownFunction(function_id)),
+        };
+
+        if self
+            .contexts
+            .limit
+            .is_some_and(|limit| self.contexts() + self.contexts.finished.len() >= limit)
+        {
+            return Err(ExecutionError::ContextLimitExceeded);
+        }
+
+        let mut stack = self.memory.stack.empty();
+        stack.expand(frame_size)?;
+
+        // Functions with frames smaller than a word don't take the argument
+        if stack.len() >= UWord::SIZE as UWord {
+            stack.set(0, argument.to_bytes().borrow())?;
+        }
+
+        let id = self.contexts.next_id;
+        self.contexts.next_id = id.wrapping_add(1);
+        self.contexts.ready.push_back(Context {
+            id,
+            stack,
+            program_counter: 0,
+            call_stack: vec![FunctionCall {
+                function_id,
+                callee: Callee::Function(frame_size),
+                base_ptr: 0,
+                ret_val_ptr: 0,
+                ret_program_counter: 0,
+            }],
+            prepared_call: false,
+            parameter_ptr: 0,
+        });
+
+        Ok(id)
+    }
+
+    /// Id of the current context.
+    pub fn context(&self) -> UWord {
+        self.contexts.current
+    }
+
+    /// Number of contexts which are not finished.
+    pub fn contexts(&self) -> usize {
+        self.contexts.suspended() + 1
+    }
+
+    /// Returns the exit value of the finished context and forgets it.
+    pub fn join(&mut self, id: UWord) -> Option<UWord> {
+        self.contexts.finished.remove(&id)
+    }
+
+    /// Swaps the state of the current context with the `context`.
+    fn swap_context(&mut self, context: &mut Context) {
+        use std::mem::swap;
+
+        swap(&mut self.memory.stack, &mut context.stack);
+        swap(&mut self.program_counter, &mut context.program_counter);
+        swap(&mut self.call_stack, &mut context.call_stack);
+        swap(&mut self.prepared_call, &mut context.prepared_call);
+        swap(&mut self.parameter_ptr, &mut context.parameter_ptr);
+        swap(&mut self.contexts.current, &mut context.id);
+    }
+
+    /// Suspends the current context and resumes the next ready one. If no context
+    /// is ready, the earliest sleeping one is resumed and the run stops with `Sleep`
+    /// for the time until it wakes. A context that yields alone keeps running.
+    ///
+    /// Fails with `Deadlock` if no context would be left to run.
+    pub(super) fn switch_context(&mut self, suspend: Suspend) -> Executed {
+        let current = self.contexts.current;
+        let contexts = &self.contexts;
+        let can_resume = !contexts.ready.is_empty()
+            || !contexts.sleeping.is_empty()
+            || match suspend {
+                Suspend::Ready | Suspend::Sleep(_) => true,
+                Suspend::Join(_) => false,
+                Suspend::Finish(_) => contexts.joining.iter().any(|&(id, _)| id == current),
+            };
+
+        if !can_resume {
+            return Err(ExecutionError::Deadlock);
+        }
+
+        if suspend == Suspend::Ready && self.contexts.ready.is_empty() {
+            return Ok(ExecutionSuccess::Ok);
+        }
+
+        let mut context = Context {
+            id: current,
+            stack: self.memory.stack.empty(),
+            program_counter: 0,
+            call_stack: Vec::new(),
+            prepared_call: false,
+            parameter_ptr: 0,
+        };
+
+        self.swap_context(&mut context);
+
+        match suspend {
+            Suspend::Ready => self.contexts.ready.push_back(context),
+            Suspend::Sleep(ticks) => {
+                let wake = self.contexts.time.saturating_add(ticks as u64);
+                self.contexts.sleeping.push((wake, context));
+            }
+            Suspend::Join(id) => self.contexts.joining.push((id, context)),
+            Suspend::Finish(val) => {
+                self.contexts.finished.insert(current, val);
+
+                // Joining contexts are ready once the context finishes
+                let (ready, joining) = std::mem::take(&mut self.contexts.joining)
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|&(id, _)| id == current);
+
+                self.contexts.joining = joining;
+                self.contexts
+                    .ready
+                    .extend(ready.into_iter().map(|(_, c)| c));
+            }
+        }
+
+        let slept = match self.contexts.ready.is_empty() {
+            true => Some(self.contexts.wake_sleeping()),
+            false => None,
+        };
+
+        let mut next = self
+            .contexts
+            .ready
+            .pop_front()
+            .expect("a context to resume");
+
+        self.swap_context(&mut next);
+
+        Ok(match slept {
+            Some(ticks) => ExecutionSuccess::Sleep(ticks as UWord),
+            None => ExecutionSuccess::Ok,
+        })
+    }
+
+    /// Finishes a spawned context with the `exit` value and resumes the next one.
+    /// Returns `None` for the context 0, which can't be finished.
+    pub(super) fn finish_context(&mut self, exit: UWord) -> Option<Executed> {
+        if self.contexts.current == 0 {
+            return None;
+        }
+
+        Some(self.switch_context(Suspend::Finish(exit)))
+    }
+
+    /// Yields to the next context when the current file would block. If all ready
+    /// contexts blocked one after another, the earliest sleeping context is resumed
+    /// and the run stops with `Sleep`, or the run stops with `Blocked` if no context
+    /// sleeps. The operation is repeated on resume and isn't counted until then.
+    pub(super) fn block(&mut self) -> Executed {
+        self.refund_op();
+
+        // No operation was done since the last block
+        let (blocked, instructions) = self.contexts.blocked;
+        let blocked = if instructions == self.instructions {
+            blocked + 1
+        } else {
+            1
+        };
+
+        if blocked > self.contexts.ready.len() {
+            // On resume every context tries again
+            self.contexts.blocked = (0, 0);
+
+            if self.contexts.sleeping.is_empty() {
+                return Ok(ExecutionSuccess::Blocked(self.files.current()?));
+            }
+
+            let ticks = self.contexts.wake_sleeping();
+            self.switch_context(Suspend::Ready)?;
+            return Ok(ExecutionSuccess::Sleep(ticks as UWord));
+        }
+
+        self.contexts.blocked = (blocked, self.instructions);
+        self.switch_context(Suspend::Ready)
+    }
+
+    pub(super) fn exec_spw(
+        &mut self,
+        x: Operand,
+        y: Operand,
+        z: Operand,
+    ) -> Result<(), ExecutionError> {
+        let id = self.spawn(self.get_val(y)?, self.get_val(z)?)?;
+        self.set_val(x, id)
+    }
+
+    /// Sets the exit value of the finished context and moves to the next operation.
+    /// Otherwise the current context waits until the context finishes and repeats
+    /// the operation when it's resumed. The context 0 never finishes, so it can't
+    /// be joined.
+    pub(super) fn exec_jon(&mut self, x: Operand, y: Operand) -> Executed {
+        let id = self.get_val(x)?;
+
+        if let Some(&val) = self.contexts.finished.get(&id) {
+            self.set_val(y, val)?;
+            self.contexts.finished.remove(&id);
+            self.program_counter = self.program_counter.wrapping_add(1);
+            return Ok(ExecutionSuccess::Ok);
+        }
+
+        if id == self.contexts.current || id == 0 {
+            return Err(ExecutionError::IncorrectOperation(*self.current_op()?));
+        }
+
+        if !self.contexts.contains(id) {
+            return Err(ExecutionError::UnknownContext(id));
+        }
+
+        let executed = self.switch_context(Suspend::Join(id));
+
+        // The operation is repeated when the context is resumed
+        if executed.is_ok() {
+            self.refund_op();
+        }
+
+        executed
+    }
+}
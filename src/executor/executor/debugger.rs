@@ -0,0 +1,30 @@
+use crate::common::UWord;
+
+/// Position of an operation in the program.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Location {
+    pub function_id: UWord,
+    pub program_counter: UWord,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Step {
+    /// Stop after the next operation.
+    Into,
+
+    /// Stop after the next operation of the current function.
+    Over,
+
+    /// Stop after the current function returns.
+    Out,
+}
+
+impl Step {
+    pub(super) fn is_done(self, start_depth: usize, depth: usize) -> bool {
+        match self {
+            Step::Into => true,
+            Step::Over => depth <= start_depth,
+            Step::Out => depth < start_depth,
+        }
+    }
+}
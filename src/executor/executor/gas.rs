@@ -0,0 +1,142 @@
+use super::{ExecutionError, Executor};
+use crate::common::UWord;
+
+/// Costs of operations in gas.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GasTable {
+    costs: [u64; 256],
+    byte_cost: u64,
+}
+
+impl GasTable {
+    /// Creates a table where every operation costs `cost` and memory
+    /// operations don't cost anything per byte.
+    pub fn new(cost: u64) -> Self {
+        Self {
+            costs: [cost; 256],
+            byte_cost: 0,
+        }
+    }
+
+    pub fn set_cost(&mut self, op_code: u8, cost: u64) {
+        self.costs[op_code as usize] = cost;
+    }
+
+    pub fn cost(&self, op_code: u8) -> u64 {
+        self.costs[op_code as usize]
+    }
+
+    /// Sets the cost of a byte processed by `cpy`, `zer` and `cmp` or transferred
+    /// by `inb` and `otb`.
+    pub fn set_byte_cost(&mut self, cost: u64) {
+        self.byte_cost = cost;
+    }
+
+    pub fn byte_cost(&self) -> u64 {
+        self.byte_cost
+    }
+}
+
+impl Default for GasTable {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct Gas {
+    table: GasTable,
+    pub(super) limit: u64,
+    pub(super) used: u64,
+    /// Gas charged for the last operation.
+    charged: u64,
+}
+
+impl Executor {
+    /// Enables gas metering. Execution fails with `OutOfGas` when the next
+    /// operation costs more than is left of the `limit`. Operations which would
+    /// block are charged only when they're done.
+    pub fn set_gas(&mut self, table: GasTable, limit: u64) {
+        self.gas = Some(Gas {
+            table,
+            limit,
+            used: 0,
+            charged: 0,
+        });
+    }
+
+    pub fn gas_used(&self) -> Option<u64> {
+        self.gas.as_ref().map(|gas| gas.used)
+    }
+
+    pub fn gas_left(&self) -> Option<u64> {
+        self.gas.as_ref().map(|gas| gas.limit - gas.used)
+    }
+
+    pub(super) fn charge_gas(&mut self) -> Result<(), ExecutionError> {
+        let gas = match &self.gas {
+            Some(gas) => gas,
+            None => return Ok(()),
+        };
+
+        // Errors of the operation are reported by its execution
+        let op = match self.current_op() {
+            Ok(&op) => op,
+            Err(_) => return Ok(()),
+        };
+
+        let cost = gas.table.cost(op.op_code());
+        let gas = self.gas.as_mut().unwrap();
+
+        if cost > gas.limit - gas.used {
+            return Err(ExecutionError::OutOfGas);
+        }
+
+        gas.used += cost;
+        gas.charged = cost;
+        Ok(())
+    }
+
+    /// Charges the `size` bytes processed by the current operation. It's called
+    /// by the operation once it has read the size, so the size is read only once.
+    /// If the bytes cost more than is left, the operation isn't counted at all.
+    pub(super) fn charge_bytes(&mut self, size: UWord) -> Result<(), ExecutionError> {
+        let gas = match &mut self.gas {
+            Some(gas) => gas,
+            None => return Ok(()),
+        };
+
+        let cost = (size as u64).saturating_mul(gas.table.byte_cost);
+
+        if cost > gas.limit - gas.used {
+            self.refund_op();
+            return Err(ExecutionError::OutOfGas);
+        }
+
+        gas.used += cost;
+        gas.charged += cost;
+        Ok(())
+    }
+
+    /// Takes back the instruction, its profile record and the gas charged for the
+    /// current operation, which is repeated when its context is resumed.
+    pub(super) fn refund_op(&mut self) {
+        self.instructions -= 1;
+        self.unprofile();
+
+        if let Some(gas) = &mut self.gas {
+            gas.used -= gas.charged;
+            gas.charged = 0;
+        }
+    }
+
+    /// Takes back the gas charged for the `bytes` of the current operation which
+    /// were not transferred.
+    pub(super) fn refund_bytes(&mut self, bytes: u64) {
+        if let Some(gas) = &mut self.gas {
+            let refund = bytes.saturating_mul(gas.table.byte_cost).min(gas.charged);
+            gas.used -= refund;
+            gas.charged -= refund;
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use super::{ExecutionError, Memory, MemoryError, Primary};
+use crate::common::UWord;
+use std::borrow::Cow;
+
+/// Access to the memory from a host function.
+pub struct HostCall<'m> {
+    memory: &'m mut Memory,
+    base_ptr: UWord,
+    parameters_size: UWord,
+    ret_val_ptr: UWord,
+}
+
+impl<'m> HostCall<'m> {
+    pub(super) fn new(
+        memory: &'m mut Memory,
+        base_ptr: UWord,
+        parameters_size: UWord,
+        ret_val_ptr: UWord,
+    ) -> Self {
+        Self {
+            memory,
+            base_ptr,
+            parameters_size,
+            ret_val_ptr,
+        }
+    }
+
+    /// Bytes written by `par` operations. They're copied if they cross pages of
+    /// the paged storage.
+    pub fn parameters(&self) -> Result<Cow<'_, [u8]>, MemoryError> {
+        self.memory.read(self.base_ptr, self.parameters_size)
+    }
+
+    /// Reads the parameter placed at `offset` of the parameter bytes.
+    pub fn parameter<T>(&self, offset: UWord) -> Result<T, MemoryError>
+    where
+        T: Primary,
+    {
+        if offset.saturating_add(T::SIZE as UWord) > self.parameters_size {
+            return Err(MemoryError::SegmentationFault(offset, T::SIZE as UWord));
+        }
+
+        self.memory.get(self.base_ptr.wrapping_add(offset))
+    }
+
+    pub fn get<T>(&self, ptr: UWord) -> Result<T, MemoryError>
+    where
+        T: Primary,
+    {
+        self.memory.get(ptr)
+    }
+
+    pub fn set<T>(&mut self, ptr: UWord, value: T) -> Result<(), MemoryError>
+    where
+        T: Primary,
+    {
+        self.memory.set(ptr, value)
+    }
+
+    pub fn slice(&self, ptr: UWord, size: UWord) -> Result<&[u8], MemoryError> {
+        self.memory.slice(ptr, size)
+    }
+
+    /// Reads bytes like `slice`, but also from devices and across pages of the
+    /// paged storage. See `Memory::read`.
+    pub fn read(&self, ptr: UWord, size: UWord) -> Result<Cow<'_, [u8]>, MemoryError> {
+        self.memory.read(ptr, size)
+    }
+
+    /// Writes the result of the call like the `ret` operation does.
+    pub fn ret<T>(&mut self, value: T) -> Result<(), MemoryError>
+    where
+        T: Primary,
+    {
+        self.memory.set(self.ret_val_ptr, value)
+    }
+}
+
+type HostFn = dyn FnMut(&mut HostCall) -> Result<(), ExecutionError> + Send;
+
+pub(super) struct HostFunction {
+    pub parameters_size: UWord,
+    pub f: Box<HostFn>,
+}
+
+impl std::fmt::Debug for HostFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostFunction")
+            .field("parameters_size", &self.parameters_size)
+            .finish()
+    }
+}
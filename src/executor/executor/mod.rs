@@ -1,12 +1,37 @@
+mod backtrace;
+mod compiled;
+mod context;
+mod debugger;
+mod gas;
+mod host;
+mod profiler;
+mod program;
+mod snapshot;
 #[cfg(test)]
 mod tests;
+mod tracer;
+
+pub use backtrace::Backtrace;
+pub use debugger::{Location, Step};
+pub use gas::GasTable;
+pub use host::HostCall;
+pub use profiler::{FunctionProfile, Profiler};
+pub use program::Program;
+pub use tracer::{Trace, TracedOperand, Tracer};
 
 use super::{
-    files::{Files, FilesError},
+    device::Device,
+    files::{FileError, FileResolver, Files, FilesError},
     memory::*,
+    paging::Permission,
     primary::*,
 };
 use crate::common::*;
+use compiled::Compiled;
+use context::{Contexts, Suspend};
+use gas::Gas;
+use host::HostFunction;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 pub struct Function<'f> {
@@ -14,14 +39,47 @@ pub struct Function<'f> {
     program: &'f [Op],
 }
 
+impl<'f> Function<'f> {
+    pub fn new(frame_size: UWord, program: &'f [Op]) -> Self {
+        Self {
+            frame_size,
+            program,
+        }
+    }
+
+    pub fn frame_size(&self) -> UWord {
+        self.frame_size
+    }
+
+    pub fn program(&self) -> &'f [Op] {
+        self.program
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Callee {
+    Function(UWord),
+    Host(UWord),
+}
+
 #[derive(Debug)]
-pub struct FunctionCall<'f> {
-    function: &'f Function<'f>,
+pub struct FunctionCall {
+    function_id: UWord,
+    callee: Callee,
     base_ptr: UWord,
     ret_val_ptr: UWord,
     ret_program_counter: UWord,
 }
 
+impl FunctionCall {
+    fn frame_size(&self) -> UWord {
+        match self.callee {
+            Callee::Function(frame_size) => frame_size,
+            Callee::Host(parameters_size) => parameters_size,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ExecutionError {
     EndOfProgram,
@@ -29,9 +87,19 @@ pub enum ExecutionError {
     FilesError(FilesError),
     IncorrectOperation(Op),
     UnknownFunction(UWord),
+    UnknownValue(UWord),
     OperationOverflow,
     DivisionByZero,
     NullPointerDereference,
+    InstructionLimitExceeded,
+    CallDepthLimitExceeded,
+    WriteLimitExceeded,
+    FilesLimitExceeded,
+    OutOfGas,
+    UnknownContext(UWord),
+    ContextLimitExceeded,
+    /// All contexts wait for contexts which can't finish.
+    Deadlock,
 }
 
 impl From<MemoryError> for ExecutionError {
@@ -42,28 +110,127 @@ impl From<MemoryError> for ExecutionError {
 
 impl From<FilesError> for ExecutionError {
     fn from(e: FilesError) -> Self {
-        ExecutionError::FilesError(e)
+        match e {
+            FilesError::LimitExceeded => ExecutionError::FilesLimitExceeded,
+            FilesError::WriteLimitExceeded => ExecutionError::WriteLimitExceeded,
+            e => ExecutionError::FilesError(e),
+        }
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ExecutionSuccess {
     Ok,
     End(UWord),
     Sleep(UWord),
+    /// The current file would block, the operation is repeated on the next run.
+    Blocked(UWord),
 }
 
 pub type Executed = Result<ExecutionSuccess, ExecutionError>;
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    End(UWord),
+    Sleep(UWord),
+    Blocked(UWord),
+    BudgetExhausted,
+    Breakpoint(Location),
+    Watchpoint(UWord),
+    Step,
+}
+
+pub type Stopped = Result<StopReason, ExecutionError>;
+
+/// Resource limits of an executor.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Size of the stack in bytes.
+    pub stack: usize,
+
+    /// Size of the heap in bytes.
+    pub heap: usize,
+
+    /// Number of operations the executor can execute.
+    pub instructions: Option<u64>,
+
+    /// Number of frames in the call stack.
+    pub call_depth: Option<usize>,
+
+    /// Number of bytes written to files.
+    pub written: Option<u64>,
+
+    /// Number of open files.
+    pub files: usize,
+
+    /// Number of contexts which are not joined. Finished contexts count until their
+    /// exit value is taken, so they can't pile up.
+    pub contexts: Option<usize>,
+
+    /// Allocates the stack and heap by pages on the first write, see `Memory::paged`.
+    pub paged: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            stack: 2048,
+            heap: 2048,
+            instructions: None,
+            call_depth: None,
+            written: None,
+            files: Files::DEFAULT_LIMIT,
+            contexts: Some(Limits::DEFAULT_CONTEXTS),
+            paged: false,
+        }
+    }
+}
+
+impl Limits {
+    pub const DEFAULT_CONTEXTS: usize = 1024;
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Arithmetic {
+    Wrapping,
+    Checked,
+    Saturating,
+}
+
+impl Arithmetic {
+    fn of(op: Op) -> Self {
+        use Op::*;
+
+        match op {
+            Adc(..) | Sbc(..) | Mlc(..) => Arithmetic::Checked,
+            Ads(..) | Sbs(..) | Mls(..) => Arithmetic::Saturating,
+            _ => Arithmetic::Wrapping,
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct Executor<'f> {
-    functions: &'f [Function<'f>],
+pub struct Executor {
+    program: Program,
     memory: Memory,
     program_counter: UWord,
-    call_stack: Vec<FunctionCall<'f>>,
+    call_stack: Vec<FunctionCall>,
     prepared_call: bool,
     parameter_ptr: UWord,
+    contexts: Contexts,
     files: Files,
+    instructions: u64,
+    instruction_limit: Option<u64>,
+    call_depth_limit: Option<usize>,
+    gas: Option<Gas>,
+    compiled: Option<Vec<Vec<Compiled>>>,
+    host_functions: HashMap<UWord, HostFunction>,
+    breakpoints: HashSet<Location>,
+    /// Breakpoint the last run stopped on. It's skipped once, so the run can continue.
+    stopped_at: Option<Location>,
+    tracer: Option<Box<dyn Tracer>>,
+    profiler: Option<Profiler>,
+    backtrace: Option<Backtrace>,
 }
 
 macro_rules! impl_cnv {
@@ -85,45 +252,127 @@ macro_rules! impl_cnv {
     };
 }
 
-impl<'f> Executor<'f> {
-    pub fn new(functions: &'f [Function]) -> Self {
-        const STACK_LIMIT: usize = 2048;
-        const HEAP_LIMIT: usize = 2048;
+impl Executor {
+    pub const VERSION: UWord = 1;
 
-        Self::from_limits(functions, STACK_LIMIT, HEAP_LIMIT)
+    /// Creates an executor with a copy of the `functions`, so the executor doesn't
+    /// borrow them. Every call copies all operations of the program, so executors of
+    /// the same functions should share a `Program` instead, see `from_shared`.
+    pub fn new(functions: &[Function]) -> Self {
+        Self::with_limits(functions, Limits::default())
     }
 
-    pub fn from_limits(functions: &'f [Function], stack_limit: usize, heap_limit: usize) -> Self {
-        Self {
+    /// Creates an executor that shares the functions of the `program` with it.
+    pub fn from_shared(program: &Program) -> Self {
+        Self::from_program(program.clone(), Limits::default())
+    }
+
+    /// Creates an executor with a copy of the `functions` like `new` does.
+    pub fn from_limits(functions: &[Function], stack_limit: usize, heap_limit: usize) -> Self {
+        Self::with_limits(
             functions,
-            memory: Memory::from_limits(stack_limit, heap_limit),
+            Limits {
+                stack: stack_limit,
+                heap: heap_limit,
+                ..Limits::default()
+            },
+        )
+    }
+
+    /// Creates an executor with a copy of the `functions` like `new` does. To share
+    /// a program between executors with limits, see `from_program`.
+    pub fn with_limits(functions: &[Function], limits: Limits) -> Self {
+        Self::from_program(Program::from_functions(functions), limits)
+    }
+
+    /// Creates an executor of the `program`. Clones of a program share its
+    /// functions, so it isn't copied.
+    pub fn from_program(program: Program, limits: Limits) -> Self {
+        Self {
+            program,
+            memory: if limits.paged {
+                Memory::paged(limits.stack, limits.heap)
+            } else {
+                Memory::from_limits(limits.stack, limits.heap)
+            },
             program_counter: 0,
             call_stack: Vec::new(),
             prepared_call: false,
             parameter_ptr: 0,
-            files: Files::new(),
+            contexts: Contexts::new(limits.contexts),
+            files: Files::from_limits(limits.files, limits.written),
+            instructions: 0,
+            instruction_limit: limits.instructions,
+            call_depth_limit: limits.call_depth,
+            gas: None,
+            compiled: None,
+            host_functions: HashMap::new(),
+            breakpoints: HashSet::new(),
+            stopped_at: None,
+            tracer: None,
+            profiler: None,
+            backtrace: None,
         }
     }
 
+    /// Registers a host function. Calls of `function_id` invoke `f` instead of
+    /// the program function with the same id.
+    pub fn register<F>(&mut self, function_id: UWord, parameters_size: UWord, f: F)
+    where
+        F: FnMut(&mut HostCall) -> Result<(), ExecutionError> + Send + 'static,
+    {
+        self.host_functions.insert(
+            function_id,
+            HostFunction {
+                parameters_size,
+                f: Box::new(f),
+            },
+        );
+    }
+
+    pub fn set_resolver<R>(&mut self, resolver: R)
+    where
+        R: FileResolver + 'static,
+    {
+        self.files.set_resolver(resolver)
+    }
+
     fn app(&mut self, function_id: UWord) -> Result<(), ExecutionError> {
-        let f = self
-            .functions
-            .get(function_id as usize)
-            .ok_or(ExecutionError::UnknownFunction(function_id))?;
+        if self
+            .call_depth_limit
+            .is_some_and(|limit| self.call_stack.len() >= limit)
+        {
+            return Err(ExecutionError::CallDepthLimitExceeded);
+        }
 
-        self.call_stack.push(FunctionCall {
-            function: f,
+        let call = FunctionCall {
+            function_id,
+            callee: self.callee(function_id)?,
             base_ptr: self.memory.stack.len(),
             ret_val_ptr: 0,
             ret_program_counter: 0,
-        });
+        };
+
+        let frame_size = call.frame_size();
+        self.call_stack.push(call);
 
         self.prepared_call = true;
-        self.memory.stack.expand(f.frame_size)?;
+        self.memory.stack.expand(frame_size)?;
 
         Ok(())
     }
 
+    fn callee(&self, function_id: UWord) -> Result<Callee, ExecutionError> {
+        match self.host_functions.get(&function_id) {
+            Some(host) => Ok(Callee::Host(host.parameters_size)),
+            None => self
+                .program
+                .frame_size(function_id)
+                .map(Callee::Function)
+                .ok_or(ExecutionError::UnknownFunction(function_id)),
+        }
+    }
+
     fn clf(&mut self, ret_val_ptr: UWord) -> Result<(), ExecutionError> {
         let current_fn = self
             .call_stack
@@ -133,12 +382,46 @@ impl<'f> Executor<'f> {
         current_fn.ret_val_ptr = ret_val_ptr;
         current_fn.ret_program_counter = self.program_counter.wrapping_add(1);
         self.prepared_call = false;
+        let parameter_ptr = std::mem::replace(&mut self.parameter_ptr, 0);
+
+        if let Callee::Host(_) = current_fn.callee {
+            return match self.call_host() {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    // Keep the call prepared and stay on the operation that made it,
+                    // so the call is made again when the execution resumes
+                    self.prepared_call = true;
+                    self.parameter_ptr = parameter_ptr;
+                    Err(e)
+                }
+            };
+        }
+
         self.program_counter = 0;
-        self.parameter_ptr = 0;
 
         Ok(())
     }
 
+    fn call_host(&mut self) -> Result<(), ExecutionError> {
+        let call = self.call_stack.last().ok_or(ExecutionError::EndOfProgram)?;
+        let function_id = call.function_id;
+
+        let host = self
+            .host_functions
+            .get_mut(&function_id)
+            .ok_or(ExecutionError::UnknownFunction(function_id))?;
+
+        let mut host_call = HostCall::new(
+            &mut self.memory,
+            call.base_ptr,
+            call.frame_size(),
+            call.ret_val_ptr,
+        );
+
+        (host.f)(&mut host_call)?;
+        self.ret()
+    }
+
     pub fn call(&mut self, function_id: UWord, ret_val_ptr: UWord) -> Result<(), ExecutionError> {
         self.app(function_id)?;
         self.clf(ret_val_ptr)
@@ -148,7 +431,7 @@ impl<'f> Executor<'f> {
         let current_fn = self.call_stack.pop().ok_or(ExecutionError::EndOfProgram)?;
 
         self.program_counter = current_fn.ret_program_counter;
-        self.memory.stack.narrow(current_fn.function.frame_size)?;
+        self.memory.stack.narrow(current_fn.frame_size())?;
 
         Ok(())
     }
@@ -163,10 +446,15 @@ impl<'f> Executor<'f> {
         call.ok_or(ExecutionError::EndOfProgram)
     }
 
+    fn call_program(&self, call: &FunctionCall) -> &[Op] {
+        match call.callee {
+            Callee::Function(_) => self.program.ops(call.function_id),
+            Callee::Host(_) => &[],
+        }
+    }
+
     fn current_op(&self) -> Result<&Op, ExecutionError> {
-        self.current_call()?
-            .function
-            .program
+        self.call_program(self.current_call()?)
             .get(self.program_counter as usize)
             .ok_or(ExecutionError::EndOfProgram)
     }
@@ -286,6 +574,18 @@ impl<'f> Executor<'f> {
         self.set_val(left, f(self.get_val(left)?, self.get_val(right)?))
     }
 
+    fn update_bin_checked<T, F>(&mut self, bin: BinOp, f: F) -> Result<(), ExecutionError>
+    where
+        T: Primary,
+        F: FnOnce(T, T) -> Option<T>,
+    {
+        let (left, right) = self.read_bin_operands(bin)?;
+        let val = f(self.get_val(left)?, self.get_val(right)?)
+            .ok_or(ExecutionError::OperationOverflow)?;
+
+        self.set_val(left, val)
+    }
+
     fn update_bin_division<T, F>(&mut self, bin: BinOp, f: F) -> Result<(), ExecutionError>
     where
         T: Primary + PartialEq,
@@ -318,7 +618,8 @@ impl<'f> Executor<'f> {
     where
         T: Primary,
     {
-        self.update_bin::<T, T, _>(bin, |_, y| y)
+        let (left, right) = self.read_bin_operands(bin)?;
+        self.set_val(left, self.get_val::<T>(right)?)
     }
 
     fn exec_cnv<T, U>(&mut self, left: Operand, right: Operand) -> Result<(), ExecutionError>
@@ -329,25 +630,37 @@ impl<'f> Executor<'f> {
         self.set_val(left, U::convert(self.get_val(right)?))
     }
 
-    fn exec_add<T>(&mut self, bin: BinOp) -> Result<(), ExecutionError>
+    fn exec_add<T>(&mut self, bin: BinOp, arithmetic: Arithmetic) -> Result<(), ExecutionError>
     where
         T: Add,
     {
-        self.update_bin::<T, T, _>(bin, |x, y| x.wrapping(y))
+        match arithmetic {
+            Arithmetic::Wrapping => self.update_bin::<T, T, _>(bin, |x, y| x.wrapping(y)),
+            Arithmetic::Checked => self.update_bin_checked::<T, _>(bin, |x, y| x.checked(y)),
+            Arithmetic::Saturating => self.update_bin::<T, T, _>(bin, |x, y| x.saturating(y)),
+        }
     }
 
-    fn exec_sub<T>(&mut self, bin: BinOp) -> Result<(), ExecutionError>
+    fn exec_sub<T>(&mut self, bin: BinOp, arithmetic: Arithmetic) -> Result<(), ExecutionError>
     where
         T: Sub,
     {
-        self.update_bin::<T, T, _>(bin, |x, y| x.wrapping(y))
+        match arithmetic {
+            Arithmetic::Wrapping => self.update_bin::<T, T, _>(bin, |x, y| x.wrapping(y)),
+            Arithmetic::Checked => self.update_bin_checked::<T, _>(bin, |x, y| x.checked(y)),
+            Arithmetic::Saturating => self.update_bin::<T, T, _>(bin, |x, y| x.saturating(y)),
+        }
     }
 
-    fn exec_mul<T>(&mut self, bin: BinOp) -> Result<(), ExecutionError>
+    fn exec_mul<T>(&mut self, bin: BinOp, arithmetic: Arithmetic) -> Result<(), ExecutionError>
     where
         T: Mul,
     {
-        self.update_bin::<T, T, _>(bin, |x, y| x.wrapping(y))
+        match arithmetic {
+            Arithmetic::Wrapping => self.update_bin::<T, T, _>(bin, |x, y| x.wrapping(y)),
+            Arithmetic::Checked => self.update_bin_checked::<T, _>(bin, |x, y| x.checked(y)),
+            Arithmetic::Saturating => self.update_bin::<T, T, _>(bin, |x, y| x.saturating(y)),
+        }
     }
 
     fn exec_div<T>(&mut self, bin: BinOp) -> Result<(), ExecutionError>
@@ -531,7 +844,7 @@ impl<'f> Executor<'f> {
     where
         T: Primary,
     {
-        let frame_size = self.current_call()?.function.frame_size;
+        let frame_size = self.current_call()?.frame_size();
         let parameter_loc = self.parameter_ptr.wrapping_add(frame_size);
         self.parameter_ptr = self.parameter_ptr.wrapping_add(T::SIZE as UWord);
 
@@ -541,29 +854,167 @@ impl<'f> Executor<'f> {
         Ok(())
     }
 
-    fn set_ret<T>(&mut self, un: UnOp) -> Result<(), ExecutionError>
+    fn interpreter_value(&self, id: UWord) -> Result<UWord, ExecutionError> {
+        use giv_codes::*;
+
+        Ok(match id {
+            WORD_SIZE => std::mem::size_of::<UWord>() as UWord,
+            STACK_LIMIT => self.memory.stack.limit() as UWord,
+            HEAP_LIMIT => self.memory.heap.limit() as UWord,
+            STACK_LEN => self.memory.stack.len(),
+            HEAP_BASE => Memory::HEAP_BASE,
+            FILES_LIMIT => self.files.limit() as UWord,
+            CALL_DEPTH => self.call_depth() as UWord,
+            VERSION => Self::VERSION,
+            _ => return Err(ExecutionError::UnknownValue(id)),
+        })
+    }
+
+    /// Sets the return value and returns its bytes as a word. Smaller values are
+    /// zero-extended and larger ones are truncated.
+    fn set_ret<T>(&mut self, un: UnOp) -> Result<UWord, ExecutionError>
     where
         T: Primary,
     {
+        use std::borrow::Borrow;
+
         let right = self.read_un_operand(un)?;
-        self.set_val::<T>(Operand::Ret(0), self.get_val(right)?)
+        let val: T = self.get_val(right)?;
+        let bytes = val.to_bytes();
+        let bytes: &[u8] = bytes.borrow();
+        let mut word = [0; UWord::SIZE];
+        let len = bytes.len().min(UWord::SIZE);
+        word[..len].copy_from_slice(&bytes[..len]);
+
+        self.set_val::<T>(Operand::Ret(0), val)?;
+        Ok(UWord::from_le_bytes(word))
+    }
+
+    /// Reads the little-endian value and sets the number of bytes read.
+    /// Returns `false` if the file would block until all bytes of the value are
+    /// available.
+    ///
+    /// The count is short only if the file ends. Then only the bytes read are set
+    /// and the rest of the value is zero.
+    fn exec_inv<T>(&mut self, bin: BinOp) -> Result<bool, ExecutionError>
+    where
+        T: Primary,
+    {
+        let (left, right) = self.read_bin_operands(bin)?;
+        let mut buf = [0; 16];
+        let buf = &mut buf[..T::SIZE];
+
+        let len = match self.files.read_value(buf) {
+            Err(FilesError::FileError(FileError::WouldBlock)) => return Ok(false),
+            len => len?,
+        };
+
+        if right != Operand::Emp {
+            self.set_val(right, len as u8)?;
+        }
+
+        self.set_val(left, T::from_slice(&buf[..len]))?;
+        Ok(true)
+    }
+
+    /// Writes the little-endian value and sets the number of bytes written.
+    /// Returns `false` if the file would block until there is room for all bytes
+    /// of the value, so no part of it is written twice.
+    fn exec_otv<T>(&mut self, bin: BinOp) -> Result<bool, ExecutionError>
+    where
+        T: Primary,
+    {
+        use std::borrow::Borrow;
+
+        let (left, right) = self.read_bin_operands(bin)?;
+        let val: T = self.get_val(left)?;
+
+        let len = match self.files.write_value(val.to_bytes().borrow()) {
+            Err(FilesError::FileError(FileError::WouldBlock)) => return Ok(false),
+            len => len?,
+        };
+
+        if right != Operand::Emp {
+            self.set_val(right, len as u8)?;
+        }
+
+        Ok(true)
     }
 
     pub fn execute(&mut self) -> Executed {
+        let program_counter = self.program_counter;
+        let executed = self.execute_metered();
+
+        if let Err(e) = executed {
+            self.capture_backtrace(e, program_counter);
+        }
+
+        executed
+    }
+
+    /// Counts the operation against the limits and executes it.
+    fn execute_metered(&mut self) -> Executed {
+        if self
+            .instruction_limit
+            .is_some_and(|limit| self.instructions >= limit)
+        {
+            return Err(ExecutionError::InstructionLimitExceeded);
+        }
+
+        self.charge_gas()?;
+
+        self.instructions += 1;
+
+        if let Some(profiler) = self.profiler.take() {
+            self.profile(profiler);
+        }
+
+        match self.tracer.take() {
+            None => self.execute_op(),
+            Some(tracer) => self.execute_traced(tracer),
+        }
+    }
+
+    fn execute_op(&mut self) -> Executed {
+        let function_id = self.current_call()?.function_id;
+
+        match self.compiled_op(function_id) {
+            Some(&op) => op.execute(self),
+            None => {
+                let &op = self.current_op()?;
+                self.interpret(op)
+            }
+        }
+    }
+
+    fn interpret(&mut self, op: Op) -> Executed {
         use Op::*;
         use OpType::*;
 
-        let &op = self.current_op()?;
-
         let res = match op {
             Nop => Ok(ExecutionSuccess::Ok),
             End(x) => {
                 let val = self.get_val(x)?;
+
+                if let Some(executed) = self.finish_context(val) {
+                    return executed;
+                }
+
                 Ok(ExecutionSuccess::End(val))
             }
             Slp(x) => {
                 let val = self.get_val(x)?;
-                Ok(ExecutionSuccess::Sleep(val))
+                self.program_counter = self.program_counter.wrapping_add(1);
+
+                // Zero sleeps yield to the next ready context. Other contexts keep
+                // running while this one sleeps, the executor sleeps only when all
+                // of them sleep, see `switch_context`.
+                let suspend = match val {
+                    0 if !self.contexts.ready.is_empty() => Suspend::Ready,
+                    _ => Suspend::Sleep(val),
+                };
+
+                return self.switch_context(suspend);
             }
             Set(bin, ot) => {
                 match ot {
@@ -601,56 +1052,62 @@ impl<'f> Executor<'f> {
 
                 Ok(ExecutionSuccess::Ok)
             }
-            Add(bin, ot) => {
+            Add(bin, ot) | Adc(bin, ot) | Ads(bin, ot) => {
+                let arithmetic = Arithmetic::of(op);
+
                 match ot {
-                    U8 => self.exec_add::<u8>(bin)?,
-                    I8 => self.exec_add::<i8>(bin)?,
-                    U16 => self.exec_add::<u16>(bin)?,
-                    I16 => self.exec_add::<i16>(bin)?,
-                    U32 => self.exec_add::<u32>(bin)?,
-                    I32 => self.exec_add::<i32>(bin)?,
-                    U64 => self.exec_add::<u64>(bin)?,
-                    I64 => self.exec_add::<i64>(bin)?,
-                    Uw => self.exec_add::<UWord>(bin)?,
-                    Iw => self.exec_add::<IWord>(bin)?,
-                    F32 => self.exec_add::<f32>(bin)?,
-                    F64 => self.exec_add::<f64>(bin)?,
+                    U8 => self.exec_add::<u8>(bin, arithmetic)?,
+                    I8 => self.exec_add::<i8>(bin, arithmetic)?,
+                    U16 => self.exec_add::<u16>(bin, arithmetic)?,
+                    I16 => self.exec_add::<i16>(bin, arithmetic)?,
+                    U32 => self.exec_add::<u32>(bin, arithmetic)?,
+                    I32 => self.exec_add::<i32>(bin, arithmetic)?,
+                    U64 => self.exec_add::<u64>(bin, arithmetic)?,
+                    I64 => self.exec_add::<i64>(bin, arithmetic)?,
+                    Uw => self.exec_add::<UWord>(bin, arithmetic)?,
+                    Iw => self.exec_add::<IWord>(bin, arithmetic)?,
+                    F32 => self.exec_add::<f32>(bin, arithmetic)?,
+                    F64 => self.exec_add::<f64>(bin, arithmetic)?,
                 }
 
                 Ok(ExecutionSuccess::Ok)
             }
-            Sub(bin, ot) => {
+            Sub(bin, ot) | Sbc(bin, ot) | Sbs(bin, ot) => {
+                let arithmetic = Arithmetic::of(op);
+
                 match ot {
-                    U8 => self.exec_sub::<u8>(bin)?,
-                    I8 => self.exec_sub::<i8>(bin)?,
-                    U16 => self.exec_sub::<u16>(bin)?,
-                    I16 => self.exec_sub::<i16>(bin)?,
-                    U32 => self.exec_sub::<u32>(bin)?,
-                    I32 => self.exec_sub::<i32>(bin)?,
-                    U64 => self.exec_sub::<u64>(bin)?,
-                    I64 => self.exec_sub::<i64>(bin)?,
-                    Uw => self.exec_sub::<UWord>(bin)?,
-                    Iw => self.exec_sub::<IWord>(bin)?,
-                    F32 => self.exec_sub::<f32>(bin)?,
-                    F64 => self.exec_sub::<f64>(bin)?,
+                    U8 => self.exec_sub::<u8>(bin, arithmetic)?,
+                    I8 => self.exec_sub::<i8>(bin, arithmetic)?,
+                    U16 => self.exec_sub::<u16>(bin, arithmetic)?,
+                    I16 => self.exec_sub::<i16>(bin, arithmetic)?,
+                    U32 => self.exec_sub::<u32>(bin, arithmetic)?,
+                    I32 => self.exec_sub::<i32>(bin, arithmetic)?,
+                    U64 => self.exec_sub::<u64>(bin, arithmetic)?,
+                    I64 => self.exec_sub::<i64>(bin, arithmetic)?,
+                    Uw => self.exec_sub::<UWord>(bin, arithmetic)?,
+                    Iw => self.exec_sub::<IWord>(bin, arithmetic)?,
+                    F32 => self.exec_sub::<f32>(bin, arithmetic)?,
+                    F64 => self.exec_sub::<f64>(bin, arithmetic)?,
                 }
 
                 Ok(ExecutionSuccess::Ok)
             }
-            Mul(bin, ot) => {
+            Mul(bin, ot) | Mlc(bin, ot) | Mls(bin, ot) => {
+                let arithmetic = Arithmetic::of(op);
+
                 match ot {
-                    U8 => self.exec_mul::<u8>(bin)?,
-                    I8 => self.exec_mul::<i8>(bin)?,
-                    U16 => self.exec_mul::<u16>(bin)?,
-                    I16 => self.exec_mul::<i16>(bin)?,
-                    U32 => self.exec_mul::<u32>(bin)?,
-                    I32 => self.exec_mul::<i32>(bin)?,
-                    U64 => self.exec_mul::<u64>(bin)?,
-                    I64 => self.exec_mul::<i64>(bin)?,
-                    Uw => self.exec_mul::<UWord>(bin)?,
-                    Iw => self.exec_mul::<IWord>(bin)?,
-                    F32 => self.exec_mul::<f32>(bin)?,
-                    F64 => self.exec_mul::<f64>(bin)?,
+                    U8 => self.exec_mul::<u8>(bin, arithmetic)?,
+                    I8 => self.exec_mul::<i8>(bin, arithmetic)?,
+                    U16 => self.exec_mul::<u16>(bin, arithmetic)?,
+                    I16 => self.exec_mul::<i16>(bin, arithmetic)?,
+                    U32 => self.exec_mul::<u32>(bin, arithmetic)?,
+                    I32 => self.exec_mul::<i32>(bin, arithmetic)?,
+                    U64 => self.exec_mul::<u64>(bin, arithmetic)?,
+                    I64 => self.exec_mul::<i64>(bin, arithmetic)?,
+                    Uw => self.exec_mul::<UWord>(bin, arithmetic)?,
+                    Iw => self.exec_mul::<IWord>(bin, arithmetic)?,
+                    F32 => self.exec_mul::<f32>(bin, arithmetic)?,
+                    F64 => self.exec_mul::<f64>(bin, arithmetic)?,
                 }
 
                 Ok(ExecutionSuccess::Ok)
@@ -1206,7 +1663,7 @@ impl<'f> Executor<'f> {
                 return Ok(ExecutionSuccess::Ok);
             }
             Ret(un, ot) => {
-                if un.x() != Operand::Emp {
+                let exit = if un.x() != Operand::Emp {
                     match ot {
                         U8 => self.set_ret::<u8>(un)?,
                         I8 => self.set_ret::<i8>(un)?,
@@ -1221,13 +1678,27 @@ impl<'f> Executor<'f> {
                         F32 => self.set_ret::<f32>(un)?,
                         F64 => self.set_ret::<f64>(un)?,
                     }
-                }
+                } else {
+                    0
+                };
 
                 self.ret()?;
+
+                // Returning from the first function finishes a spawned context
+                // with the returned value
+                if self.call_stack.is_empty() {
+                    if let Some(executed) = self.finish_context(exit) {
+                        return executed;
+                    }
+                }
+
                 return Ok(ExecutionSuccess::Ok);
             }
             In(bin) => {
-                let val = self.files.read()?;
+                let val = match self.files.read() {
+                    Err(FilesError::FileError(FileError::WouldBlock)) => return self.block(),
+                    val => val?,
+                };
                 let (left, right) = self.read_bin_operands(bin)?;
 
                 if right != Operand::Emp {
@@ -1239,13 +1710,114 @@ impl<'f> Executor<'f> {
             }
             Out(un) => {
                 let val = self.get_val(self.read_un_operand(un)?)?;
-                self.files.write(val)?;
+
+                match self.files.write(val) {
+                    Err(FilesError::FileError(FileError::WouldBlock)) => return self.block(),
+                    res => res?,
+                }
+
+                Ok(ExecutionSuccess::Ok)
+            }
+            Inb(x, y, z) => {
+                let ptr = self.get_val(y)?;
+                let size = self.get_val(z)?;
+                self.charge_bytes(size)?;
+
+                // Check the range before the input is consumed
+                self.memory.check_write(ptr, size)?;
+
+                let mut buf = vec![0; size as usize];
+
+                let len = if buf.is_empty() {
+                    // Reading nothing doesn't wait for the file
+                    0
+                } else {
+                    match self.files.read_bytes(&mut buf) {
+                        Err(FilesError::FileError(FileError::WouldBlock)) => return self.block(),
+                        len => len?,
+                    }
+                };
+
+                self.refund_bytes((size as usize - len) as u64);
+                self.memory.set_bytes(ptr, &buf[..len])?;
+                self.set_val(x, len as UWord)?;
+                Ok(ExecutionSuccess::Ok)
+            }
+            Otb(x, y, z) => {
+                let ptr = self.get_val(y)?;
+                let size = self.get_val(z)?;
+                self.charge_bytes(size)?;
+                let buf = self.memory.read(ptr, size)?;
+
+                let len = match self.files.write_bytes(&buf) {
+                    Err(FilesError::FileError(FileError::WouldBlock)) => return self.block(),
+                    len => len?,
+                };
+
+                self.refund_bytes((size as usize - len) as u64);
+                self.set_val(x, len as UWord)?;
+                Ok(ExecutionSuccess::Ok)
+            }
+            Inv(bin, ot) => {
+                let done = match ot {
+                    U8 => self.exec_inv::<u8>(bin)?,
+                    I8 => self.exec_inv::<i8>(bin)?,
+                    U16 => self.exec_inv::<u16>(bin)?,
+                    I16 => self.exec_inv::<i16>(bin)?,
+                    U32 => self.exec_inv::<u32>(bin)?,
+                    I32 => self.exec_inv::<i32>(bin)?,
+                    U64 => self.exec_inv::<u64>(bin)?,
+                    I64 => self.exec_inv::<i64>(bin)?,
+                    Uw => self.exec_inv::<UWord>(bin)?,
+                    Iw => self.exec_inv::<IWord>(bin)?,
+                    F32 => self.exec_inv::<f32>(bin)?,
+                    F64 => self.exec_inv::<f64>(bin)?,
+                };
+
+                if !done {
+                    return self.block();
+                }
+
+                Ok(ExecutionSuccess::Ok)
+            }
+            Otv(bin, ot) => {
+                let done = match ot {
+                    U8 => self.exec_otv::<u8>(bin)?,
+                    I8 => self.exec_otv::<i8>(bin)?,
+                    U16 => self.exec_otv::<u16>(bin)?,
+                    I16 => self.exec_otv::<i16>(bin)?,
+                    U32 => self.exec_otv::<u32>(bin)?,
+                    I32 => self.exec_otv::<i32>(bin)?,
+                    U64 => self.exec_otv::<u64>(bin)?,
+                    I64 => self.exec_otv::<i64>(bin)?,
+                    Uw => self.exec_otv::<UWord>(bin)?,
+                    Iw => self.exec_otv::<IWord>(bin)?,
+                    F32 => self.exec_otv::<f32>(bin)?,
+                    F64 => self.exec_otv::<f64>(bin)?,
+                };
+
+                if !done {
+                    return self.block();
+                }
+
                 Ok(ExecutionSuccess::Ok)
             }
             Fls => {
                 self.files.flush()?;
                 Ok(ExecutionSuccess::Ok)
             }
+            Opn(x, y, z) => {
+                let ptr = self.get_val(y)?;
+                let len = self.get_val(z)?;
+                let name = self.memory.read(ptr, len)?;
+                let fd = self.files.open_named(&name)?;
+                self.set_val(x, fd)?;
+                Ok(ExecutionSuccess::Ok)
+            }
+            Cls(x) => {
+                self.files.close(self.get_val(x)?)?;
+                Ok(ExecutionSuccess::Ok)
+            }
             Sfd(x) => {
                 self.files.set_current(self.get_val(x)?)?;
                 Ok(ExecutionSuccess::Ok)
@@ -1257,6 +1829,7 @@ impl<'f> Executor<'f> {
             Zer(x, y) => {
                 let dest = self.get_val(x)?;
                 let size = self.get_val(y)?;
+                self.charge_bytes(size)?;
                 self.memory.set_zeros(dest, size)?;
                 Ok(ExecutionSuccess::Ok)
             }
@@ -1264,6 +1837,7 @@ impl<'f> Executor<'f> {
                 let a = self.get_val(x)?;
                 let b = self.get_val(y)?;
                 let size = self.get_val(z)?;
+                self.charge_bytes(size)?;
 
                 if self.memory.compare(a, b, size)? {
                     Ok(ExecutionSuccess::Ok)
@@ -1276,9 +1850,30 @@ impl<'f> Executor<'f> {
                 let dest = self.get_val(x)?;
                 let src = self.get_val(y)?;
                 let size = self.get_val(z)?;
+                self.charge_bytes(size)?;
                 self.memory.copy(dest, src, size)?;
                 Ok(ExecutionSuccess::Ok)
             }
+            Giv(x, y) => {
+                let val = self.interpreter_value(self.get_val(y)?)?;
+                self.set_val(x, val)?;
+                Ok(ExecutionSuccess::Ok)
+            }
+            Alc(x, y) => {
+                let size = self.get_val(y)?;
+                let ptr = self.memory.alloc_at(size, self.location())?;
+                self.set_val(x, ptr)?;
+                Ok(ExecutionSuccess::Ok)
+            }
+            Fre(x) => {
+                self.memory.free_at(self.get_val(x)?, self.location())?;
+                Ok(ExecutionSuccess::Ok)
+            }
+            Spw(x, y, z) => {
+                self.exec_spw(x, y, z)?;
+                Ok(ExecutionSuccess::Ok)
+            }
+            Jon(x, y) => return self.exec_jon(x, y),
         };
 
         if res.is_ok() {
@@ -1287,4 +1882,167 @@ impl<'f> Executor<'f> {
 
         res
     }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn files(&self) -> &Files {
+        &self.files
+    }
+
+    pub fn files_mut(&mut self) -> &mut Files {
+        &mut self.files
+    }
+
+    /// Number of called functions, not counting the prepared call.
+    pub fn call_depth(&self) -> usize {
+        self.call_stack.len() - self.prepared_call as usize
+    }
+
+    /// Number of operations executed since the executor was created.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Number of bytes written to files since the executor was created.
+    pub fn written(&self) -> u64 {
+        self.files.written()
+    }
+
+    /// Location of the next operation.
+    pub fn location(&self) -> Option<Location> {
+        let call = self.current_call().ok()?;
+
+        Some(Location {
+            function_id: call.function_id,
+            program_counter: self.program_counter,
+        })
+    }
+
+    pub fn add_breakpoint(&mut self, function_id: UWord, program_counter: UWord) {
+        self.breakpoints.insert(Location {
+            function_id,
+            program_counter,
+        });
+    }
+
+    pub fn remove_breakpoint(&mut self, function_id: UWord, program_counter: UWord) -> bool {
+        self.breakpoints.remove(&Location {
+            function_id,
+            program_counter,
+        })
+    }
+
+    /// Stops the run after an operation writes to `size` bytes at `ptr`.
+    pub fn add_watchpoint(&mut self, ptr: UWord, size: UWord) {
+        self.memory.add_watchpoint(ptr, size)
+    }
+
+    pub fn remove_watchpoint(&mut self, ptr: UWord, size: UWord) -> bool {
+        self.memory.remove_watchpoint(ptr, size)
+    }
+
+    /// Makes reads of stack and heap bytes that weren't written since their frame
+    /// or allocation was created fail with `MemoryError::UninitializedRead`.
+    pub fn enable_shadow_memory(&mut self) {
+        self.memory.enable_shadow();
+
+        for context in self.contexts.ready.iter_mut() {
+            context.stack.enable_shadow();
+        }
+
+        for (_, context) in self.contexts.sleeping.iter_mut() {
+            context.stack.enable_shadow();
+        }
+
+        for (_, context) in self.contexts.joining.iter_mut() {
+            context.stack.enable_shadow();
+        }
+    }
+
+    /// Sets the `permission` of pages at `ptr`. See `Memory::protect`.
+    pub fn protect(
+        &mut self,
+        ptr: UWord,
+        size: UWord,
+        permission: Permission,
+    ) -> Result<(), MemoryError> {
+        self.memory.protect(ptr, size, permission)
+    }
+
+    /// Maps the `device` to the range at `ptr`. See `Memory::map_device`.
+    pub fn map_device<D>(&mut self, ptr: UWord, device: D) -> Result<(), MemoryError>
+    where
+        D: Device + 'static,
+    {
+        self.memory.map_device(ptr, device)
+    }
+
+    pub fn unmap_device(&mut self, ptr: UWord) -> Option<Box<dyn Device>> {
+        self.memory.unmap_device(ptr)
+    }
+
+    /// Makes heap accesses outside of live allocations fail with `MemoryError::HeapOverflow`
+    /// or `MemoryError::UseAfterFree`, reporting where the allocation was made and freed.
+    /// See `Memory::enable_sanitizer`.
+    pub fn enable_heap_sanitizer(&mut self, red_zone: UWord, quarantine: UWord) {
+        self.memory.enable_sanitizer(red_zone, quarantine)
+    }
+
+    /// Executes operations until the program ends, sleeps, fails or `budget` operations
+    /// have been executed. Next call resumes from the operation where this one stopped.
+    ///
+    /// The run also stops on breakpoints and watchpoints. The breakpoint a run stopped
+    /// on is skipped by the next run, so the stopped run can be continued.
+    pub fn run(&mut self, budget: usize) -> Stopped {
+        self.run_until(budget, None)
+    }
+
+    /// Runs like `run` until the `step` is done.
+    pub fn step(&mut self, step: Step, budget: usize) -> Stopped {
+        self.run_until(budget, Some((step, self.call_depth())))
+    }
+
+    fn run_until(&mut self, budget: usize, step: Option<(Step, usize)>) -> Stopped {
+        for _ in 0..budget {
+            let stopped_at = self.stopped_at.take();
+
+            if !self.breakpoints.is_empty() {
+                if let Some(location) = self.location() {
+                    if stopped_at != Some(location) && self.breakpoints.contains(&location) {
+                        self.stopped_at = Some(location);
+                        return Ok(StopReason::Breakpoint(location));
+                    }
+                }
+            }
+
+            let executed = self.execute();
+            let watch_hit = self.memory.take_watch_hit();
+
+            // The operation wasn't done, so its breakpoint is still skipped
+            if matches!(executed, Err(_) | Ok(ExecutionSuccess::Blocked(_))) {
+                self.stopped_at = stopped_at;
+            }
+
+            match executed? {
+                ExecutionSuccess::Ok => (),
+                ExecutionSuccess::End(val) => return Ok(StopReason::End(val)),
+                ExecutionSuccess::Sleep(val) => return Ok(StopReason::Sleep(val)),
+                ExecutionSuccess::Blocked(fd) => return Ok(StopReason::Blocked(fd)),
+            }
+
+            if let Some(ptr) = watch_hit {
+                return Ok(StopReason::Watchpoint(ptr));
+            }
+
+            if let Some((step, depth)) = step {
+                if step.is_done(depth, self.call_depth()) {
+                    return Ok(StopReason::Step);
+                }
+            }
+        }
+
+        Ok(StopReason::BudgetExhausted)
+    }
 }
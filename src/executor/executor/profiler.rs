@@ -0,0 +1,192 @@
+use super::{Executor, Location};
+use crate::common::UWord;
+use std::{collections::HashMap, io};
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct FunctionProfile {
+    /// Operations executed by the function itself.
+    pub exclusive: u64,
+
+    /// Operations executed by the function and all functions it called.
+    pub inclusive: u64,
+}
+
+/// Counter of executed operations.
+#[derive(Clone, Debug)]
+pub struct Profiler {
+    op_codes: [u64; 256],
+    locations: HashMap<Location, u64>,
+    stacks: HashMap<Vec<UWord>, u64>,
+    stack: Vec<UWord>,
+    /// Location and op code of the last recorded operation.
+    last: Option<(Location, u8)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            op_codes: [0; 256],
+            locations: HashMap::new(),
+            stacks: HashMap::new(),
+            stack: Vec::new(),
+            last: None,
+        }
+    }
+
+    fn record<I>(&mut self, stack: I, program_counter: UWord, op_code: u8)
+    where
+        I: Iterator<Item = UWord>,
+    {
+        self.last = None;
+        self.stack.clear();
+        self.stack.extend(stack);
+
+        let function_id = match self.stack.last() {
+            Some(&id) => id,
+            None => return,
+        };
+
+        let location = Location {
+            function_id,
+            program_counter,
+        };
+
+        self.op_codes[op_code as usize] += 1;
+        *self.locations.entry(location).or_insert(0) += 1;
+        self.last = Some((location, op_code));
+
+        match self.stacks.get_mut(self.stack.as_slice()) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.stack.clone(), 1);
+            }
+        }
+    }
+
+    /// Removes the last recorded operation, which wasn't done and is repeated later.
+    fn undo(&mut self) {
+        let (location, op_code) = match self.last.take() {
+            Some(last) => last,
+            None => return,
+        };
+
+        self.op_codes[op_code as usize] -= 1;
+
+        if let Some(count) = self.locations.get_mut(&location) {
+            *count -= 1;
+
+            if *count == 0 {
+                self.locations.remove(&location);
+            }
+        }
+
+        if let Some(count) = self.stacks.get_mut(self.stack.as_slice()) {
+            *count -= 1;
+
+            if *count == 0 {
+                self.stacks.remove(self.stack.as_slice());
+            }
+        }
+    }
+
+    /// Total number of executed operations.
+    pub fn instructions(&self) -> u64 {
+        self.op_codes.iter().sum()
+    }
+
+    pub fn op_code(&self, op_code: u8) -> u64 {
+        self.op_codes[op_code as usize]
+    }
+
+    pub fn location(&self, function_id: UWord, program_counter: UWord) -> u64 {
+        self.locations
+            .get(&Location {
+                function_id,
+                program_counter,
+            })
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn function(&self, function_id: UWord) -> FunctionProfile {
+        let mut profile = FunctionProfile::default();
+
+        for (stack, &count) in self.stacks.iter() {
+            if stack.last() == Some(&function_id) {
+                profile.exclusive += count;
+            }
+
+            // Recursive calls are counted once
+            if stack.contains(&function_id) {
+                profile.inclusive += count;
+            }
+        }
+
+        profile
+    }
+
+    /// Writes the collapsed stacks, one `id;id;id count` line per call stack.
+    pub fn write_collapsed<W>(&self, buf: &mut W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+
+        for (stack, count) in stacks {
+            let mut ids = stack.iter();
+
+            if let Some(id) = ids.next() {
+                write!(buf, "{}", id)?;
+            }
+
+            for id in ids {
+                write!(buf, ";{}", id)?;
+            }
+
+            writeln!(buf, " {}", count)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor {
+    pub fn enable_profiler(&mut self) {
+        if self.profiler.is_none() {
+            self.profiler = Some(Profiler::new());
+        }
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    pub(super) fn profile(&mut self, mut profiler: Profiler) {
+        if let Ok(op) = self.current_op() {
+            let depth = self.call_depth();
+            let stack = self.call_stack[..depth].iter().map(|call| call.function_id);
+            profiler.record(stack, self.program_counter, op.op_code());
+        }
+
+        self.profiler = Some(profiler);
+    }
+
+    /// Takes back the record of the current operation, which is repeated when its
+    /// context is resumed.
+    pub(super) fn unprofile(&mut self) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.undo();
+        }
+    }
+}
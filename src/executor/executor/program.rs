@@ -0,0 +1,82 @@
+use super::Function;
+use crate::common::{Op, UWord};
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+struct ProgramFunction {
+    frame_size: UWord,
+    program: Vec<Op>,
+}
+
+/// Owned functions of a program.
+///
+/// Clones share the same storage, so a program can be given to many executors.
+#[derive(Clone, Debug, Default)]
+pub struct Program {
+    functions: Arc<Vec<ProgramFunction>>,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_functions(functions: &[Function]) -> Self {
+        let functions = functions
+            .iter()
+            .map(|f| ProgramFunction {
+                frame_size: f.frame_size,
+                program: f.program.to_vec(),
+            })
+            .collect();
+
+        Self {
+            functions: Arc::new(functions),
+        }
+    }
+
+    /// Adds a function and returns its id.
+    ///
+    /// If the storage is shared, the program is copied first.
+    pub fn push(&mut self, frame_size: UWord, program: Vec<Op>) -> UWord {
+        let functions = Arc::make_mut(&mut self.functions);
+        functions.push(ProgramFunction {
+            frame_size,
+            program,
+        });
+
+        (functions.len() - 1) as UWord
+    }
+
+    pub fn function(&self, function_id: UWord) -> Option<Function<'_>> {
+        self.functions
+            .get(function_id as usize)
+            .map(|f| Function::new(f.frame_size, &f.program))
+    }
+
+    pub fn len(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    pub(super) fn frame_size(&self, function_id: UWord) -> Option<UWord> {
+        self.functions
+            .get(function_id as usize)
+            .map(|f| f.frame_size)
+    }
+
+    pub(super) fn ops(&self, function_id: UWord) -> &[Op] {
+        self.functions
+            .get(function_id as usize)
+            .map_or(&[], |f| &f.program)
+    }
+}
+
+impl From<&[Function<'_>]> for Program {
+    fn from(functions: &[Function]) -> Self {
+        Self::from_functions(functions)
+    }
+}
@@ -0,0 +1,237 @@
+use super::{context::Context, Executor, FunctionCall};
+use crate::{
+    common::UWord,
+    executor::{
+        files::File,
+        memory::Memory,
+        snapshot::{Reader, SnapshotError, Writer},
+    },
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+impl Executor {
+    /// Saves the state of the executor.
+    ///
+    /// Host functions, the resolver, breakpoints, the tracer, the profiler and mapped
+    /// devices are not saved.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+
+        w.word(self.program_counter);
+        w.bool(self.prepared_call);
+        w.word(self.parameter_ptr);
+        save_calls(&mut w, &self.call_stack);
+        self.memory.save(&mut w);
+
+        w.word(self.contexts.current);
+        w.word(self.contexts.next_id);
+        w.u64(self.contexts.time);
+        w.word(self.contexts.ready.len() as UWord);
+
+        for context in self.contexts.ready.iter() {
+            save_context(&mut w, context);
+        }
+
+        w.word(self.contexts.sleeping.len() as UWord);
+
+        for (wake, context) in self.contexts.sleeping.iter() {
+            w.u64(*wake);
+            save_context(&mut w, context);
+        }
+
+        w.word(self.contexts.joining.len() as UWord);
+
+        for (id, context) in self.contexts.joining.iter() {
+            w.word(*id);
+            save_context(&mut w, context);
+        }
+
+        let mut finished: Vec<_> = self.contexts.finished.iter().collect();
+        finished.sort_unstable();
+        w.word(finished.len() as UWord);
+
+        for (&id, &val) in finished {
+            w.word(id);
+            w.word(val);
+        }
+
+        self.files.save(&mut w);
+
+        w.u64(self.instructions);
+        w.bool(self.gas.is_some());
+
+        if let Some(gas) = &self.gas {
+            w.u64(gas.used);
+        }
+
+        w.finish()
+    }
+
+    /// Restores the state saved by `snapshot`.
+    ///
+    /// The executor must run the same functions and have the same host functions
+    /// registered. Every open file is restored by the `loader` from its descriptor
+    /// and the state saved by `FileSnapshot`, if any. Mapped devices stay mapped.
+    /// Executed instructions, written bytes and used gas count against the limits of
+    /// the executor. Used gas is restored only if the executor meters gas.
+    /// On error the executor is left unchanged.
+    pub fn restore<L>(&mut self, snapshot: &[u8], loader: L) -> Result<(), SnapshotError>
+    where
+        L: FnMut(UWord, Option<&[u8]>) -> Option<Box<dyn File>>,
+    {
+        let mut r = Reader::new(snapshot)?;
+
+        let program_counter = r.word()?;
+        let prepared_call = r.bool()?;
+        let parameter_ptr = r.word()?;
+        let call_stack = self.load_calls(&mut r, prepared_call)?;
+        let mut memory = self.memory.load(&mut r)?;
+
+        let current = r.word()?;
+        let next_id = r.word()?;
+        let time = r.u64()?;
+        let mut ids = HashSet::from([current]);
+        let mut ready = VecDeque::new();
+        let mut sleeping = Vec::new();
+        let mut joining = Vec::new();
+
+        for _ in 0..r.word()? {
+            ready.push_back(self.load_context(&mut r, &memory, &mut ids)?);
+        }
+
+        for _ in 0..r.word()? {
+            let wake = r.u64()?;
+            sleeping.push((wake, self.load_context(&mut r, &memory, &mut ids)?));
+        }
+
+        for _ in 0..r.word()? {
+            let id = r.word()?;
+            joining.push((id, self.load_context(&mut r, &memory, &mut ids)?));
+        }
+
+        let len = r.word()?;
+        let mut finished = HashMap::new();
+
+        for _ in 0..len {
+            let id = r.word()?;
+            finished.insert(id, r.word()?);
+        }
+
+        // Finished contexts count against the limit like in `spawn`
+        if self
+            .contexts
+            .limit
+            .is_some_and(|limit| ids.len() + finished.len() > limit)
+        {
+            return Err(SnapshotError::ContextLimitExceeded);
+        }
+
+        let files = self.files.load(&mut r, loader)?;
+        let instructions = r.u64()?;
+        let gas_used = if r.bool()? { Some(r.u64()?) } else { None };
+        r.finish()?;
+
+        self.files.replace(files);
+        self.instructions = instructions;
+
+        if let (Some(gas), Some(used)) = (&mut self.gas, gas_used) {
+            gas.used = used.min(gas.limit);
+        }
+
+        self.program_counter = program_counter;
+        self.prepared_call = prepared_call;
+        self.parameter_ptr = parameter_ptr;
+        self.call_stack = call_stack;
+        self.stopped_at = None;
+        memory.take_devices(&mut self.memory);
+        self.memory = memory;
+        self.contexts.current = current;
+        self.contexts.next_id = next_id;
+        self.contexts.time = time;
+        self.contexts.ready = ready;
+        self.contexts.sleeping = sleeping;
+        self.contexts.joining = joining;
+        self.contexts.finished = finished;
+
+        Ok(())
+    }
+
+    /// Loads a suspended context. Its id must not be in the `ids` of loaded contexts.
+    fn load_context(
+        &self,
+        r: &mut Reader,
+        memory: &Memory,
+        ids: &mut HashSet<UWord>,
+    ) -> Result<Context, SnapshotError> {
+        let id = r.word()?;
+
+        if !ids.insert(id) {
+            return Err(SnapshotError::InvalidData);
+        }
+
+        let program_counter = r.word()?;
+        let prepared_call = r.bool()?;
+        let parameter_ptr = r.word()?;
+        let call_stack = self.load_calls(r, prepared_call)?;
+        let mut stack = memory.stack.empty();
+        stack.load(r)?;
+
+        Ok(Context {
+            id,
+            stack,
+            program_counter,
+            call_stack,
+            prepared_call,
+            parameter_ptr,
+        })
+    }
+
+    fn load_calls(
+        &self,
+        r: &mut Reader,
+        prepared_call: bool,
+    ) -> Result<Vec<FunctionCall>, SnapshotError> {
+        let len = r.word()?;
+        let mut call_stack = Vec::new();
+
+        for _ in 0..len {
+            let function_id = r.word()?;
+
+            call_stack.push(FunctionCall {
+                function_id,
+                callee: self
+                    .callee(function_id)
+                    .map_err(|_| SnapshotError::UnknownFunction(function_id))?,
+                base_ptr: r.word()?,
+                ret_val_ptr: r.word()?,
+                ret_program_counter: r.word()?,
+            });
+        }
+
+        if prepared_call && call_stack.is_empty() {
+            return Err(SnapshotError::InvalidData);
+        }
+
+        Ok(call_stack)
+    }
+}
+
+fn save_context(w: &mut Writer, context: &Context) {
+    w.word(context.id);
+    w.word(context.program_counter);
+    w.bool(context.prepared_call);
+    w.word(context.parameter_ptr);
+    save_calls(w, &context.call_stack);
+    context.stack.save(w);
+}
+
+fn save_calls(w: &mut Writer, call_stack: &[FunctionCall]) {
+    w.word(call_stack.len() as UWord);
+
+    for call in call_stack {
+        w.word(call.function_id);
+        w.word(call.base_ptr);
+        w.word(call.ret_val_ptr);
+        w.word(call.ret_program_counter);
+    }
+}
@@ -712,3 +712,1800 @@ fn executor_cpy() {
     assert_eq!(exe.get_val::<u32>(Operand::Loc(0)), Ok(0x10EF));
     assert_eq!(exe.get_val::<u32>(Operand::Loc(4)), Ok(0x10EF));
 }
+
+#[test]
+fn executor_run() {
+    let functions = [Function {
+        frame_size: std::mem::size_of::<UWord>() as UWord,
+        program: &[
+            // loop:
+            // inc i
+            Op::Inc(UnOp::new(Operand::Loc(0)), OpType::U32),
+            // ifl i 3
+            Op::Ifl(BinOp::new(Operand::Loc(0), Operand::Val(3)), OpType::U32),
+            // go loop
+            Op::Go(Operand::Val(0)),
+            // slp 5
+            Op::Slp(Operand::Val(5)),
+            // end i
+            Op::End(Operand::Loc(0)),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(4), Stopped::Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(0)), Ok(2));
+
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::Sleep(5)));
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(0)), Ok(3));
+
+    assert_eq!(exe.run(0), Stopped::Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::End(3)));
+}
+
+#[test]
+fn executor_run_error() {
+    let functions = [Function {
+        frame_size: 4,
+        program: &[
+            Op::Set(BinOp::new(Operand::Loc(0), Operand::Val(8)), OpType::U32),
+            Op::Div(BinOp::new(Operand::Loc(0), Operand::Val(0)), OpType::U32),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(100), Stopped::Err(ExecutionError::DivisionByZero));
+    assert_eq!(exe.program_counter, 1);
+}
+
+#[test]
+fn executor_alc() {
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let functions = [Function {
+        frame_size: W * 2,
+        program: &[
+            // uw a
+            // uw b
+            // alc b 4
+            Op::Alc(Operand::Loc(W), Operand::Val(4)),
+            // set *b 12
+            Op::Set(BinOp::new(Operand::Ind(W), Operand::Val(12)), OpType::U32),
+            // alc a 4
+            Op::Alc(Operand::Loc(0), Operand::Val(4)),
+            // fre b
+            Op::Fre(Operand::Loc(W)),
+            // fre b
+            Op::Fre(Operand::Loc(W)),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    let b: UWord = exe.get_val(Operand::Loc(W)).unwrap();
+    assert_eq!(b, Memory::HEAP_BASE);
+
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    assert_eq!(exe.get_val::<u32>(Operand::Glb(b)), Ok(12));
+
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    let a: UWord = exe.get_val(Operand::Loc(0)).unwrap();
+    assert!(a > b);
+
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    assert_eq!(
+        exe.execute(),
+        Executed::Err(ExecutionError::MemoryError(MemoryError::DoubleFree(b)))
+    );
+}
+
+#[test]
+fn executor_opn_cls() {
+    use crate::executor::File;
+    use std::collections::vec_deque::VecDeque;
+
+    #[derive(Debug)]
+    struct Resolver;
+
+    impl FileResolver for Resolver {
+        fn resolve(&mut self, name: &[u8]) -> Option<Box<dyn File>> {
+            match name {
+                b"in" => Some(Box::new(VecDeque::from(vec![7]))),
+                _ => None,
+            }
+        }
+    }
+
+    let fd_loc = 3;
+    let functions = [Function {
+        frame_size: 3 + std::mem::size_of::<UWord>() as UWord,
+        program: &[
+            // u8[2] name // "in"
+            // u8 a
+            // uw fd
+            Op::Set(
+                BinOp::new(Operand::Loc(0), Operand::Val('i' as UWord)),
+                OpType::U8,
+            ),
+            Op::Set(
+                BinOp::new(Operand::Loc(1), Operand::Val('n' as UWord)),
+                OpType::U8,
+            ),
+            // opn fd &name 2
+            Op::Opn(Operand::Loc(fd_loc), Operand::Ref(0), Operand::Val(2)),
+            // sfd fd
+            Op::Sfd(Operand::Loc(fd_loc)),
+            // in a
+            Op::In(BinOp::new(Operand::Loc(2), Operand::Emp)),
+            // cls fd
+            Op::Cls(Operand::Loc(fd_loc)),
+            // opn fd &name 1
+            Op::Opn(Operand::Loc(fd_loc), Operand::Ref(0), Operand::Val(1)),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.set_resolver(Resolver);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(6), Stopped::Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(2)), Ok(7));
+    assert_eq!(exe.files.current(), Err(FilesError::CurrentIsNotSet));
+
+    assert_eq!(
+        exe.execute(),
+        Executed::Err(ExecutionError::FilesError(FilesError::NotFound))
+    );
+}
+
+#[test]
+fn executor_giv() {
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let functions = [Function {
+        frame_size: W * 3,
+        program: &[
+            Op::Giv(Operand::Loc(0), Operand::Val(giv_codes::WORD_SIZE)),
+            Op::Giv(Operand::Loc(W), Operand::Val(giv_codes::STACK_LIMIT)),
+            Op::Giv(Operand::Loc(W * 2), Operand::Val(giv_codes::CALL_DEPTH)),
+            Op::Giv(Operand::Loc(0), Operand::Val(0xFF)),
+        ],
+    }];
+
+    let mut exe = Executor::from_limits(&functions, 512, 1024);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(3), Stopped::Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<UWord>(Operand::Loc(0)), Ok(W));
+    assert_eq!(exe.get_val::<UWord>(Operand::Loc(W)), Ok(512));
+    assert_eq!(exe.get_val::<UWord>(Operand::Loc(W * 2)), Ok(1));
+    assert_eq!(
+        exe.execute(),
+        Executed::Err(ExecutionError::UnknownValue(0xFF))
+    );
+}
+
+#[test]
+fn executor_host_function() {
+    let functions = [Function {
+        frame_size: 4,
+        program: &[
+            // u32 result
+            // app sum
+            Op::App(Operand::Val(1)),
+            // par 2
+            Op::Par(UnOp::new(Operand::Val(2)), OpType::U32),
+            // par 3
+            Op::Par(UnOp::new(Operand::Val(3)), OpType::U32),
+            // clf &result
+            Op::Clf(Operand::Ref(0)),
+            // app fail
+            Op::App(Operand::Val(2)),
+            // clf &result
+            Op::Clf(Operand::Ref(0)),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.register(1, 8, |call| {
+        let a: u32 = call.parameter(0)?;
+        let b: u32 = call.parameter(4)?;
+        call.ret(a + b)?;
+        Ok(())
+    });
+    exe.register(2, 0, |_| Err(ExecutionError::DivisionByZero));
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(4), Stopped::Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(0)), Ok(5));
+    assert_eq!(exe.call_stack.len(), 1);
+    assert_eq!(exe.memory.stack.len(), 4);
+
+    assert_eq!(exe.run(2), Stopped::Err(ExecutionError::DivisionByZero));
+    assert_eq!(exe.program_counter, 5);
+    assert_eq!(exe.call_depth(), 1);
+    assert_eq!(exe.memory.stack.len(), 4);
+}
+
+#[test]
+fn executor_host_function_retry() {
+    let functions = [Function {
+        frame_size: std::mem::size_of::<UWord>() as UWord,
+        program: &[
+            Op::Set(BinOp::new(Operand::Loc(0), Operand::Val(1)), OpType::U32),
+            Op::App(Operand::Val(1)),
+            Op::Par(UnOp::new(Operand::Val(21)), OpType::U32),
+            Op::Clf(Operand::Ref(0)),
+            Op::End(Operand::Loc(0)),
+        ],
+    }];
+
+    let mut attempts = 0;
+    let mut exe = Executor::new(&functions);
+    exe.register(1, 4, move |call| {
+        attempts += 1;
+
+        if attempts == 1 {
+            return Err(ExecutionError::DivisionByZero);
+        }
+
+        let a: u32 = call.parameter(0)?;
+        call.ret(a * 2)?;
+        Ok(())
+    });
+    exe.call(0, 0).unwrap();
+
+    // The failed call stays prepared with its parameters
+    assert_eq!(exe.run(100), Stopped::Err(ExecutionError::DivisionByZero));
+    assert_eq!(exe.program_counter, 3);
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(0)), Ok(1));
+
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::End(42)));
+}
+
+#[test]
+fn executor_breakpoints() {
+    let functions = [
+        Function {
+            frame_size: std::mem::size_of::<UWord>() as UWord,
+            program: &[
+                Op::App(Operand::Val(1)),
+                Op::Clf(Operand::Ref(0)),
+                Op::Inc(UnOp::new(Operand::Loc(0)), OpType::U32),
+                Op::End(Operand::Loc(0)),
+            ],
+        },
+        Function {
+            frame_size: 0,
+            program: &[
+                Op::Set(BinOp::new(Operand::Ret(0), Operand::Val(7)), OpType::U32),
+                Op::Ret(UnOp::new(Operand::Emp), OpType::U8),
+            ],
+        },
+    ];
+
+    let mut exe = Executor::new(&functions);
+    exe.add_breakpoint(1, 1);
+    exe.add_breakpoint(0, 3);
+    exe.call(0, 0).unwrap();
+
+    let location = Location {
+        function_id: 1,
+        program_counter: 1,
+    };
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::Breakpoint(location)));
+    assert_eq!(exe.location(), Some(location));
+    assert_eq!(exe.call_depth(), 2);
+
+    let location = Location {
+        function_id: 0,
+        program_counter: 3,
+    };
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::Breakpoint(location)));
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(0)), Ok(8));
+
+    assert!(exe.remove_breakpoint(0, 3));
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::End(8)));
+
+    let functions = [Function {
+        frame_size: 0,
+        program: &[Op::Nop, Op::Nop, Op::Nop, Op::End(Operand::Val(0))],
+    }];
+
+    // A run that stops right before a breakpoint doesn't step over it
+    let mut exe = Executor::new(&functions);
+    exe.add_breakpoint(0, 2);
+    exe.call(0, 0).unwrap();
+
+    let stops: Vec<_> = std::iter::repeat_with(|| exe.run(1)).take(5).collect();
+    assert_eq!(
+        stops,
+        [
+            Stopped::Ok(StopReason::BudgetExhausted),
+            Stopped::Ok(StopReason::BudgetExhausted),
+            Stopped::Ok(StopReason::Breakpoint(Location {
+                function_id: 0,
+                program_counter: 2,
+            })),
+            Stopped::Ok(StopReason::BudgetExhausted),
+            Stopped::Ok(StopReason::End(0)),
+        ]
+    );
+}
+
+#[test]
+fn executor_step() {
+    let functions = [
+        Function {
+            frame_size: 4,
+            program: &[
+                Op::App(Operand::Val(1)),
+                Op::Clf(Operand::Ref(0)),
+                Op::Inc(UnOp::new(Operand::Loc(0)), OpType::U32),
+                Op::End(Operand::Loc(0)),
+            ],
+        },
+        Function {
+            frame_size: 0,
+            program: &[
+                Op::Set(BinOp::new(Operand::Ret(0), Operand::Val(7)), OpType::U32),
+                Op::Nop,
+                Op::Ret(UnOp::new(Operand::Emp), OpType::U8),
+            ],
+        },
+    ];
+
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+
+    let location = |function_id, program_counter| {
+        Some(Location {
+            function_id,
+            program_counter,
+        })
+    };
+
+    assert_eq!(exe.step(Step::Into, 100), Stopped::Ok(StopReason::Step));
+    assert_eq!(exe.step(Step::Into, 100), Stopped::Ok(StopReason::Step));
+    assert_eq!(exe.location(), location(1, 0));
+
+    assert_eq!(exe.step(Step::Over, 100), Stopped::Ok(StopReason::Step));
+    assert_eq!(exe.location(), location(1, 1));
+
+    assert_eq!(exe.step(Step::Out, 100), Stopped::Ok(StopReason::Step));
+    assert_eq!(exe.location(), location(0, 2));
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(0)), Ok(7));
+
+    exe.program_counter = 0;
+    assert_eq!(exe.step(Step::Over, 100), Stopped::Ok(StopReason::Step));
+    assert_eq!(exe.step(Step::Over, 100), Stopped::Ok(StopReason::Step));
+    assert_eq!(exe.location(), location(0, 2));
+}
+
+#[test]
+fn executor_watchpoints() {
+    let functions = [Function {
+        frame_size: 8,
+        program: &[
+            Op::Set(BinOp::new(Operand::Loc(0), Operand::Val(1)), OpType::U32),
+            Op::Set(BinOp::new(Operand::Loc(4), Operand::Val(2)), OpType::U32),
+            Op::Cpy(Operand::Val(0), Operand::Val(4), Operand::Val(4)),
+            Op::End(Operand::Val(0)),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.add_watchpoint(0, 2);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::Watchpoint(0)));
+    assert_eq!(exe.location().unwrap().program_counter, 1);
+
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::Watchpoint(0)));
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(0)), Ok(2));
+
+    assert!(exe.remove_watchpoint(0, 2));
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::End(0)));
+}
+
+#[test]
+fn executor_tracer() {
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct Log {
+        before: Vec<Trace>,
+        after: Vec<(Trace, Executed)>,
+    }
+
+    #[derive(Debug)]
+    struct LogTracer(Arc<Mutex<Log>>);
+
+    impl Tracer for LogTracer {
+        fn before(&mut self, trace: &Trace) {
+            self.0.lock().unwrap().before.push(*trace);
+        }
+
+        fn after(&mut self, trace: &Trace, executed: &Executed) {
+            self.0.lock().unwrap().after.push((*trace, *executed));
+        }
+    }
+
+    let functions = [Function {
+        frame_size: 8 + std::mem::size_of::<UWord>() as UWord,
+        program: &[
+            Op::Set(BinOp::new(Operand::Loc(8), Operand::Val(4)), OpType::U32),
+            Op::Add(
+                BinOp::new(Operand::Loc(0), Operand::Val(3)).with_first(Operand::Loc(8)),
+                OpType::U32,
+            ),
+            Op::End(Operand::Val(0)),
+        ],
+    }];
+
+    let log = Arc::new(Mutex::new(Log::default()));
+    let mut exe = Executor::new(&functions);
+    exe.memory.stack.expand(4).unwrap();
+    exe.call(0, 0).unwrap();
+    exe.set_tracer(LogTracer(Arc::clone(&log)));
+
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::End(0)));
+
+    let log = log.lock().unwrap();
+    assert_eq!(log.before.len(), 3);
+    assert_eq!(log.after.len(), 3);
+
+    let add = log.before[1];
+    assert_eq!(
+        add.location,
+        Location {
+            function_id: 0,
+            program_counter: 1
+        }
+    );
+    assert_eq!(
+        add.operands[0],
+        Some(TracedOperand {
+            operand: Operand::Loc(4),
+            size: 4,
+            address: Some(8),
+            value: Some(0),
+        })
+    );
+    assert_eq!(add.operands[1].unwrap().value, Some(3));
+    assert_eq!(add.operands[2], None);
+
+    let (add, executed) = &log.after[1];
+    assert_eq!(add.operands[0].unwrap().value, Some(3));
+    assert_eq!(executed, &Executed::Ok(ExecutionSuccess::Ok));
+}
+
+#[test]
+fn executor_profiler() {
+    let functions = [
+        Function {
+            frame_size: std::mem::size_of::<UWord>() as UWord,
+            program: &[
+                Op::App(Operand::Val(1)),
+                Op::Clf(Operand::Ref(0)),
+                Op::Inc(UnOp::new(Operand::Loc(0)), OpType::U32),
+                Op::End(Operand::Loc(0)),
+            ],
+        },
+        Function {
+            frame_size: 0,
+            program: &[
+                Op::Set(BinOp::new(Operand::Ret(0), Operand::Val(7)), OpType::U32),
+                Op::Nop,
+                Op::Ret(UnOp::new(Operand::Emp), OpType::U8),
+            ],
+        },
+    ];
+
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+    exe.enable_profiler();
+
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::End(8)));
+
+    let profiler = exe.take_profiler().unwrap();
+    assert_eq!(profiler.instructions(), 7);
+    assert_eq!(profiler.op_code(Op::Nop.op_code()), 1);
+    assert_eq!(profiler.location(0, 1), 1);
+    assert_eq!(profiler.location(1, 2), 1);
+    assert_eq!(
+        profiler.function(0),
+        FunctionProfile {
+            exclusive: 4,
+            inclusive: 7
+        }
+    );
+    assert_eq!(
+        profiler.function(1),
+        FunctionProfile {
+            exclusive: 3,
+            inclusive: 3
+        }
+    );
+
+    let mut collapsed = Vec::new();
+    profiler.write_collapsed(&mut collapsed).unwrap();
+    assert_eq!(collapsed, b"0 4\n0;1 3\n");
+}
+
+#[test]
+fn executor_snapshot() {
+    use crate::executor::{File, SnapshotError};
+    use std::collections::vec_deque::VecDeque;
+
+    let functions = [
+        Function {
+            frame_size: std::mem::size_of::<UWord>() as UWord,
+            program: &[
+                Op::Set(BinOp::new(Operand::Loc(0), Operand::Val(5)), OpType::U32),
+                Op::App(Operand::Val(1)),
+                Op::Clf(Operand::Ref(0)),
+                Op::End(Operand::Loc(0)),
+            ],
+        },
+        Function {
+            frame_size: 0,
+            program: &[
+                Op::Inc(UnOp::new(Operand::Ret(0)), OpType::U32),
+                Op::Ret(UnOp::new(Operand::Emp), OpType::U8),
+            ],
+        },
+    ];
+
+    let mut exe = Executor::new(&functions);
+    exe.files.open(VecDeque::from(vec![1, 2])).unwrap();
+    exe.files.open(Vec::new()).unwrap();
+    exe.files.set_current(0).unwrap();
+    exe.call(0, 0).unwrap();
+
+    for _ in 0..3 {
+        assert_eq!(exe.step(Step::Into, 100), Stopped::Ok(StopReason::Step));
+    }
+
+    let snapshot = exe.snapshot();
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::End(6)));
+
+    let loader = |fd, state: Option<&[u8]>| -> Option<Box<dyn File>> {
+        match (fd, state) {
+            (0, Some(state)) => Some(Box::new(state.iter().copied().collect::<VecDeque<_>>())),
+            (1, Some(state)) => Some(Box::new(state.to_vec())),
+            _ => None,
+        }
+    };
+
+    let mut restored = Executor::new(&functions);
+    assert_eq!(
+        restored.restore(&snapshot, |_, _| None),
+        Err(SnapshotError::FileNotRestored(0))
+    );
+    assert_eq!(restored.call_depth(), 0);
+    assert_eq!(
+        restored.restore(&snapshot[..snapshot.len() - 1], loader),
+        Err(SnapshotError::UnexpectedEnd)
+    );
+
+    restored.restore(&snapshot, loader).unwrap();
+    assert_eq!(
+        restored.location(),
+        Some(Location {
+            function_id: 1,
+            program_counter: 0
+        })
+    );
+    assert_eq!(restored.files.current(), Ok(0));
+    assert_eq!(restored.files.read(), Ok(Some(1)));
+    assert_eq!(restored.run(100), Stopped::Ok(StopReason::End(6)));
+}
+
+#[test]
+fn executor_backtrace() {
+    let functions = [
+        Function {
+            frame_size: 0,
+            program: &[
+                Op::Nop,
+                Op::App(Operand::Val(1)),
+                Op::Clf(Operand::Ref(0)),
+                Op::End(Operand::Val(0)),
+            ],
+        },
+        Function {
+            frame_size: 4,
+            program: &[
+                Op::Set(BinOp::new(Operand::Loc(0), Operand::Val(1)), OpType::U32),
+                Op::Div(BinOp::new(Operand::Loc(0), Operand::Val(0)), OpType::U32),
+            ],
+        },
+    ];
+
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+    assert_eq!(exe.backtrace(), None);
+
+    assert_eq!(exe.run(100), Stopped::Err(ExecutionError::DivisionByZero));
+
+    let backtrace = exe.backtrace().unwrap();
+    assert_eq!(backtrace.error, ExecutionError::DivisionByZero);
+    assert_eq!(backtrace.op, Some(functions[1].program[1]));
+    assert_eq!(
+        backtrace.frames,
+        [
+            Location {
+                function_id: 1,
+                program_counter: 1
+            },
+            Location {
+                function_id: 0,
+                program_counter: 2
+            },
+        ]
+    );
+    assert_eq!(
+        backtrace.to_string(),
+        "DivisionByZero at `div u32 None { x: loc(0), y: val(0) }`\n   0: function 1 at 1\n   1: function 0 at 2"
+    );
+}
+
+#[test]
+fn executor_checked_saturating() {
+    let functions = [Function {
+        frame_size: 2,
+        program: &[
+            Op::Set(BinOp::new(Operand::Loc(0), Operand::Val(250)), OpType::U8),
+            Op::Ads(BinOp::new(Operand::Loc(0), Operand::Val(10)), OpType::U8),
+            Op::Adc(BinOp::new(Operand::Loc(0), Operand::Val(1)), OpType::U8),
+            Op::Sbc(BinOp::new(Operand::Loc(0), Operand::Val(5)), OpType::U8),
+            Op::Mlc(BinOp::new(Operand::Loc(0), Operand::Val(2)), OpType::U8),
+            Op::Set(BinOp::new(Operand::Loc(1), Operand::Val(100)), OpType::I8),
+            Op::Mls(
+                BinOp::new(Operand::Loc(1), Operand::Val(-2i8 as UWord)),
+                OpType::I8,
+            ),
+            Op::Sbs(BinOp::new(Operand::Loc(1), Operand::Val(1)), OpType::I8),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(0)), Ok(255));
+
+    assert_eq!(
+        exe.execute(),
+        Executed::Err(ExecutionError::OperationOverflow)
+    );
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(0)), Ok(255));
+    exe.program_counter += 1; // Move manually after overflow
+
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(0)), Ok(250));
+
+    assert_eq!(
+        exe.execute(),
+        Executed::Err(ExecutionError::OperationOverflow)
+    );
+    exe.program_counter += 1;
+
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    assert_eq!(exe.get_val::<i8>(Operand::Loc(1)), Ok(i8::MIN));
+    assert_eq!(exe.execute(), Executed::Ok(ExecutionSuccess::Ok));
+    assert_eq!(exe.get_val::<i8>(Operand::Loc(1)), Ok(i8::MIN));
+}
+
+#[test]
+fn executor_limits() {
+    let functions = [
+        Function {
+            frame_size: 0,
+            program: &[Op::Go(Operand::Val(0))],
+        },
+        Function {
+            frame_size: 0,
+            program: &[Op::App(Operand::Val(1)), Op::Clf(Operand::Ref(0))],
+        },
+        Function {
+            frame_size: 0,
+            program: &[Op::Out(UnOp::new(Operand::Val(1))), Op::Go(Operand::Val(0))],
+        },
+    ];
+
+    let limits = Limits {
+        instructions: Some(10),
+        call_depth: Some(3),
+        written: Some(2),
+        files: 1,
+        ..Limits::default()
+    };
+
+    let mut exe = Executor::with_limits(&functions, limits);
+    exe.call(0, 0).unwrap();
+    assert_eq!(
+        exe.run(100),
+        Stopped::Err(ExecutionError::InstructionLimitExceeded)
+    );
+    assert_eq!(exe.instructions(), 10);
+    assert_eq!(
+        exe.backtrace().map(|b| b.error),
+        Some(ExecutionError::InstructionLimitExceeded)
+    );
+
+    // A restored executor doesn't get a fresh budget
+    let snapshot = exe.snapshot();
+    let mut exe = Executor::with_limits(&functions, limits);
+    exe.restore(&snapshot, |_, _| None).unwrap();
+    assert_eq!(exe.instructions(), 10);
+    assert_eq!(
+        exe.run(100),
+        Stopped::Err(ExecutionError::InstructionLimitExceeded)
+    );
+
+    let mut exe = Executor::with_limits(&functions, limits);
+    exe.call(1, 0).unwrap();
+    assert_eq!(
+        exe.run(100),
+        Stopped::Err(ExecutionError::CallDepthLimitExceeded)
+    );
+    assert_eq!(exe.call_depth(), 3);
+
+    let mut exe = Executor::with_limits(&functions, limits);
+    exe.files.open(Vec::new()).unwrap();
+    exe.files.set_current(0).unwrap();
+    assert_eq!(
+        exe.files.open(Vec::new()).map_err(|(e, _)| e.into()),
+        Err(ExecutionError::FilesLimitExceeded)
+    );
+    exe.call(2, 0).unwrap();
+    assert_eq!(
+        exe.run(100),
+        Stopped::Err(ExecutionError::WriteLimitExceeded)
+    );
+    assert_eq!(exe.written(), 2);
+}
+
+#[test]
+fn executor_gas() {
+    use crate::executor::Device;
+
+    let functions = [Function {
+        frame_size: 8,
+        program: &[
+            Op::Set(BinOp::new(Operand::Loc(0), Operand::Val(1)), OpType::U32),
+            Op::Cpy(Operand::Val(4), Operand::Val(0), Operand::Val(4)),
+            Op::Go(Operand::Val(1)),
+        ],
+    }];
+
+    let mut table = GasTable::new(1);
+    table.set_cost(op_codes::SET, 2);
+    table.set_byte_cost(1);
+
+    let mut exe = Executor::new(&functions);
+    assert_eq!(exe.gas_used(), None);
+
+    exe.set_gas(table, 20);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(100), Stopped::Err(ExecutionError::OutOfGas));
+    assert_eq!(exe.gas_used(), Some(20));
+    assert_eq!(exe.gas_left(), Some(0));
+    assert_eq!(exe.program_counter, 1);
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(4)), Ok(1));
+
+    let backtrace = exe.backtrace().unwrap();
+    assert_eq!(backtrace.error, ExecutionError::OutOfGas);
+    assert_eq!(
+        backtrace.frames,
+        [Location {
+            function_id: 0,
+            program_counter: 1,
+        }]
+    );
+
+    // Used gas is restored from the snapshot
+    let snapshot = exe.snapshot();
+    let mut restored = Executor::new(&functions);
+    restored.set_gas(GasTable::new(1), 30);
+    restored.restore(&snapshot, |_, _| None).unwrap();
+    assert_eq!(restored.gas_left(), Some(10));
+
+    #[derive(Debug)]
+    struct Counter(std::cell::Cell<UWord>);
+
+    impl Device for Counter {
+        fn size(&self) -> UWord {
+            UWord::SIZE as UWord
+        }
+
+        fn read(&self, _: UWord, buf: &mut [u8]) {
+            let val = self.0.get();
+            buf.copy_from_slice(&val.to_le_bytes()[..buf.len()]);
+            self.0.set(val + 1);
+        }
+
+        fn write(&mut self, _: UWord, _: &[u8]) {}
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    const COUNTER: UWord = 4096;
+
+    let functions = [Function {
+        frame_size: 8,
+        program: &[
+            Op::Zer(Operand::Val(0), Operand::Glb(COUNTER)),
+            Op::End(Operand::Val(0)),
+        ],
+    }];
+
+    let mut table = GasTable::new(1);
+    table.set_byte_cost(1);
+
+    // The size is read once and charged as it's used
+    let mut exe = Executor::new(&functions);
+    exe.map_device(COUNTER, Counter(std::cell::Cell::new(4)))
+        .unwrap();
+    exe.set_gas(table, 20);
+    exe.call(0, 0).unwrap();
+    assert_eq!(exe.run(1), Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.gas_used(), Some(5));
+
+    let counter = exe.unmap_device(COUNTER).unwrap();
+    assert_eq!(
+        counter.as_any().downcast_ref::<Counter>().unwrap().0.get(),
+        5
+    );
+}
+
+#[test]
+fn executor_contexts() {
+    use crate::executor::SnapshotError;
+
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let functions = [
+        Function {
+            frame_size: 3 * W,
+            program: &[
+                Op::Alc(Operand::Loc(2 * W), Operand::Val(W)),
+                Op::Spw(Operand::Loc(0), Operand::Val(1), Operand::Loc(2 * W)),
+                Op::Spw(Operand::Loc(W), Operand::Val(2), Operand::Loc(2 * W)),
+                Op::Jon(Operand::Loc(0), Operand::Loc(0)),
+                Op::Jon(Operand::Loc(W), Operand::Loc(W)),
+                Op::End(Operand::Ind(2 * W)),
+            ],
+        },
+        Function {
+            frame_size: 2 * W,
+            program: &[
+                Op::Set(BinOp::new(Operand::Loc(W), Operand::Loc(0)), OpType::Uw),
+                Op::Inc(UnOp::new(Operand::Ind(W)), OpType::Uw),
+                Op::Slp(Operand::Val(0)),
+                Op::Inc(UnOp::new(Operand::Ind(W)), OpType::Uw),
+                Op::End(Operand::Val(7)),
+            ],
+        },
+        Function {
+            frame_size: 2 * W,
+            program: &[
+                Op::Set(BinOp::new(Operand::Loc(W), Operand::Loc(0)), OpType::Uw),
+                Op::Inc(UnOp::new(Operand::Ind(W)), OpType::Uw),
+                Op::Ret(UnOp::new(Operand::Emp), OpType::Uw),
+            ],
+        },
+        Function {
+            frame_size: W,
+            program: &[Op::Slp(Operand::Val(5)), Op::End(Operand::Val(1))],
+        },
+    ];
+
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(4), Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.context(), 1);
+    assert_eq!(exe.contexts(), 3);
+
+    // The waiting join is counted only when it's done
+    assert_eq!(exe.instructions(), 3);
+
+    assert_eq!(exe.run(3), Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.context(), 2);
+
+    let snapshot = exe.snapshot();
+    assert_eq!(exe.run(100), Ok(StopReason::End(3)));
+    assert_eq!(exe.get_val::<UWord>(Operand::Loc(0)), Ok(7));
+    assert_eq!(exe.get_val::<UWord>(Operand::Loc(W)), Ok(0));
+    assert_eq!(exe.contexts(), 1);
+
+    let mut restored = Executor::new(&functions);
+    restored.restore(&snapshot, |_, _| None).unwrap();
+    assert_eq!(restored.context(), 2);
+    assert_eq!(restored.run(100), Ok(StopReason::End(3)));
+
+    let mut exe = Executor::new(&functions);
+    exe.call(1, 0).unwrap();
+    assert_eq!(exe.run(100), Ok(StopReason::Sleep(0)));
+    assert_eq!(exe.run(100), Ok(StopReason::End(7)));
+
+    // The executor sleeps once all contexts sleep
+    let mut exe = Executor::new(&functions);
+    exe.call(3, 0).unwrap();
+    exe.spawn(3, 0).unwrap();
+    assert_eq!(exe.run(100), Ok(StopReason::Sleep(5)));
+    assert_eq!(exe.context(), 0);
+    assert_eq!(exe.contexts(), 2);
+    assert_eq!(exe.run(100), Ok(StopReason::End(1)));
+
+    let limits = Limits {
+        contexts: Some(2),
+        ..Limits::default()
+    };
+
+    let mut exe = Executor::with_limits(&functions, limits);
+    exe.call(3, 0).unwrap();
+    assert_eq!(exe.spawn(2, 0), Ok(1));
+    assert_eq!(exe.spawn(1, 0), Err(ExecutionError::ContextLimitExceeded));
+    assert_eq!(exe.spawn(4, 0), Err(ExecutionError::UnknownFunction(4)));
+    assert_eq!(exe.join(1), None);
+
+    // The finished context counts until it's joined
+    assert_eq!(exe.run(100), Ok(StopReason::Sleep(5)));
+    assert_eq!(exe.run(100), Ok(StopReason::End(1)));
+    assert_eq!(exe.contexts(), 1);
+    assert_eq!(exe.spawn(2, 0), Err(ExecutionError::ContextLimitExceeded));
+    assert_eq!(exe.join(1), Some(0));
+    assert_eq!(exe.spawn(2, 0), Ok(2));
+
+    // Restored contexts count against the limit too
+    let mut exe = Executor::new(&functions);
+    exe.call(3, 0).unwrap();
+    exe.spawn(2, 0).unwrap();
+    exe.spawn(2, 0).unwrap();
+    let snapshot = exe.snapshot();
+
+    let mut restored = Executor::with_limits(&functions, limits);
+    assert_eq!(
+        restored.restore(&snapshot, |_, _| None),
+        Err(SnapshotError::ContextLimitExceeded)
+    );
+}
+
+#[test]
+fn executor_context_wait() {
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let functions = [
+        Function {
+            frame_size: 2 * W,
+            program: &[
+                Op::Spw(Operand::Loc(0), Operand::Val(1), Operand::Val(2)),
+                Op::Slp(Operand::Val(5)),
+                Op::Jon(Operand::Loc(0), Operand::Loc(0)),
+                Op::End(Operand::Loc(0)),
+            ],
+        },
+        Function {
+            frame_size: W,
+            program: &[Op::Slp(Operand::Loc(0)), Op::End(Operand::Val(7))],
+        },
+        Function {
+            frame_size: W,
+            program: &[
+                Op::Jon(Operand::Loc(0), Operand::Loc(0)),
+                Op::End(Operand::Val(0)),
+            ],
+        },
+        Function {
+            frame_size: 2 * W,
+            program: &[
+                Op::Spw(Operand::Loc(0), Operand::Val(2), Operand::Val(2)),
+                Op::Spw(Operand::Loc(W), Operand::Val(2), Operand::Val(1)),
+                Op::Jon(Operand::Loc(0), Operand::Loc(0)),
+            ],
+        },
+        Function {
+            frame_size: W,
+            program: &[Op::Ret(UnOp::new(Operand::Val(9)), OpType::U8)],
+        },
+    ];
+
+    // Other contexts run while one sleeps, the executor sleeps until the earliest wakes
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+    assert_eq!(exe.run(100), Ok(StopReason::Sleep(2)));
+    assert_eq!(exe.context(), 1);
+    assert_eq!(exe.instructions(), 3);
+    assert_eq!(exe.run(100), Ok(StopReason::Sleep(3)));
+    assert_eq!(exe.context(), 0);
+
+    let snapshot = exe.snapshot();
+    assert_eq!(exe.run(100), Ok(StopReason::End(7)));
+
+    let mut restored = Executor::new(&functions);
+    restored.restore(&snapshot, |_, _| None).unwrap();
+    assert_eq!(restored.run(100), Ok(StopReason::End(7)));
+
+    // Contexts joining each other wait until no context is left to run
+    let mut exe = Executor::new(&functions);
+    exe.call(3, 0).unwrap();
+    assert_eq!(exe.run(100), Err(ExecutionError::Deadlock));
+    assert_eq!(exe.context(), 2);
+    assert_eq!(exe.contexts(), 3);
+
+    // The context 0 never finishes
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+    exe.spawn(2, 0).unwrap();
+    exe.run(2).unwrap();
+    assert_eq!(exe.context(), 1);
+    assert!(matches!(
+        exe.run(1),
+        Err(ExecutionError::IncorrectOperation(Op::Jon(..)))
+    ));
+
+    // Returning from the first function sets the exit value like `End`
+    let mut exe = Executor::new(&functions);
+    exe.call(1, 0).unwrap();
+    let id = exe.spawn(4, 0).unwrap();
+    assert_eq!(exe.run(100), Ok(StopReason::End(7)));
+    assert_eq!(exe.join(id), Some(9));
+}
+
+#[test]
+fn executor_would_block() {
+    use crate::executor::{pipe, File, FileError};
+    use std::{
+        any::Any,
+        collections::vec_deque::VecDeque,
+        sync::{Arc, Mutex},
+    };
+
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    #[derive(Debug)]
+    struct Socket(Arc<Mutex<VecDeque<u8>>>);
+
+    impl File for Socket {
+        fn read(&mut self) -> Result<Option<u8>, FileError> {
+            let val = self.0.lock().unwrap().pop_front();
+            val.map(Some).ok_or(FileError::WouldBlock)
+        }
+
+        fn write(&mut self, _: u8) -> Result<(), FileError> {
+            Err(FileError::WouldBlock)
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    let functions = [Function {
+        frame_size: 2,
+        program: &[
+            Op::In(BinOp::new(Operand::Loc(0), Operand::Loc(1))),
+            Op::Out(UnOp::new(Operand::Loc(0))),
+        ],
+    }];
+
+    let data = Arc::new(Mutex::new(VecDeque::new()));
+    let mut exe = Executor::new(&functions);
+    exe.files.open(Socket(Arc::clone(&data))).unwrap();
+    exe.files.set_current(0).unwrap();
+    exe.set_gas(GasTable::new(1), 2);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(100), Ok(StopReason::Blocked(0)));
+    assert_eq!(exe.run(100), Ok(StopReason::Blocked(0)));
+    assert_eq!(exe.program_counter, 0);
+
+    // Blocked operations are counted only when they're done
+    assert_eq!(exe.instructions(), 0);
+    assert_eq!(exe.gas_used(), Some(0));
+
+    data.lock().unwrap().push_back(42);
+    assert_eq!(exe.run(100), Ok(StopReason::Blocked(0)));
+    assert_eq!(exe.program_counter, 1);
+    assert_eq!(exe.instructions(), 1);
+    assert_eq!(exe.gas_used(), Some(1));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(0)), Ok(42));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(1)), Ok(1));
+
+    // The run stops once all contexts are blocked
+    let mut exe = Executor::new(&functions);
+    exe.files.open(Socket(Arc::clone(&data))).unwrap();
+    exe.files.set_current(0).unwrap();
+    exe.call(0, 0).unwrap();
+    exe.spawn(0, 0).unwrap();
+    exe.spawn(0, 0).unwrap();
+
+    assert_eq!(exe.run(100), Ok(StopReason::Blocked(0)));
+    assert_eq!(exe.instructions(), 0);
+    assert_eq!(exe.context(), 2);
+
+    // The context 2 reads and blocks on the output, others block on the input
+    data.lock().unwrap().push_back(7);
+    assert_eq!(exe.run(100), Ok(StopReason::Blocked(0)));
+    assert_eq!(exe.instructions(), 1);
+    assert_eq!(exe.context(), 1);
+    assert!(data.lock().unwrap().is_empty());
+
+    let functions = [Function {
+        frame_size: W + 1,
+        program: &[
+            Op::In(BinOp::new(Operand::Loc(0), Operand::Loc(W))),
+            Op::End(Operand::Loc(0)),
+        ],
+    }];
+
+    // Blocked operations are profiled only when they're done
+    let (mut writer, reader) = pipe(1);
+    let mut exe = Executor::new(&functions);
+    exe.files.open(reader).unwrap();
+    exe.files.set_current(0).unwrap();
+    exe.enable_profiler();
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(100), Ok(StopReason::Blocked(0)));
+    assert_eq!(exe.run(100), Ok(StopReason::Blocked(0)));
+    assert_eq!(exe.profiler().unwrap().instructions(), 0);
+
+    writer.write(5).unwrap();
+    assert_eq!(exe.run(100), Ok(StopReason::End(5)));
+
+    let profiler = exe.profiler().unwrap();
+    assert_eq!(profiler.instructions(), exe.instructions());
+    assert_eq!(profiler.location(0, 0), 1);
+    assert_eq!(profiler.function(0).exclusive, 2);
+}
+
+#[test]
+fn executor_bulk_io() {
+    use std::collections::vec_deque::VecDeque;
+
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let functions = [Function {
+        frame_size: 2 * W + 8,
+        program: &[
+            // sfd 0
+            Op::Sfd(Operand::Val(0)),
+            // inb n &buf 8
+            Op::Inb(Operand::Loc(0), Operand::Ref(2 * W), Operand::Val(8)),
+            // inv u32 x m
+            Op::Inv(
+                BinOp::new(Operand::Loc(2 * W + 4), Operand::Loc(W)),
+                OpType::U32,
+            ),
+            // sfd 1
+            Op::Sfd(Operand::Val(1)),
+            // otb n &buf n
+            Op::Otb(Operand::Loc(0), Operand::Ref(2 * W), Operand::Loc(0)),
+            // otv u16 buf m
+            Op::Otv(
+                BinOp::new(Operand::Loc(2 * W), Operand::Loc(W)),
+                OpType::U16,
+            ),
+        ],
+    }];
+
+    let mut table = GasTable::new(1);
+    table.set_byte_cost(1);
+
+    let mut exe = Executor::new(&functions);
+    exe.files.open(VecDeque::from(vec![1, 2, 3])).unwrap();
+    exe.files.open(Vec::new()).unwrap();
+    exe.set_gas(table, 100);
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(3), Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<UWord>(Operand::Loc(0)), Ok(3));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(W)), Ok(0));
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(2 * W)), Ok(0x030201));
+
+    // Only the transferred bytes are charged
+    assert_eq!(exe.gas_used(), Some(6));
+
+    assert_eq!(exe.run(3), Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<UWord>(Operand::Loc(0)), Ok(3));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(W)), Ok(2));
+    assert_eq!(exe.written(), 5);
+    assert_eq!(exe.gas_used(), Some(12));
+
+    let output = exe.files.close(1).unwrap();
+    assert_eq!(
+        output.as_any().downcast_ref::<Vec<u8>>().unwrap(),
+        &[1, 2, 3, 1, 2]
+    );
+}
+
+#[test]
+fn executor_short_input() {
+    use crate::executor::{pipe, File, FileError};
+
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let functions = [Function {
+        frame_size: 2 * W + 4,
+        program: &[
+            // inb n &buf 0
+            Op::Inb(Operand::Loc(0), Operand::Ref(2 * W), Operand::Val(0)),
+            // inv u32 buf n
+            Op::Inv(
+                BinOp::new(Operand::Loc(2 * W), Operand::Loc(0)),
+                OpType::U32,
+            ),
+            // inv u32 buf n
+            Op::Inv(
+                BinOp::new(Operand::Loc(2 * W), Operand::Loc(0)),
+                OpType::U32,
+            ),
+        ],
+    }];
+
+    let (mut writer, reader) = pipe(4);
+    let mut exe = Executor::new(&functions);
+    exe.files.open(reader).unwrap();
+    exe.files.set_current(0).unwrap();
+    exe.call(0, 0).unwrap();
+    exe.set_val(Operand::Loc(0), UWord::MAX).unwrap();
+
+    // The empty pipe doesn't block reading nothing
+    assert_eq!(exe.run(1), Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<UWord>(Operand::Loc(0)), Ok(0));
+    assert_eq!(exe.run(1), Ok(StopReason::Blocked(0)));
+
+    // The value is read only when all its bytes are available
+    writer.write(1).unwrap();
+    assert_eq!(exe.run(1), Ok(StopReason::Blocked(0)));
+    writer.write_bytes(&[2, 3, 4]).unwrap();
+    assert_eq!(exe.run(1), Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(0)), Ok(4));
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(2 * W)), Ok(0x04030201));
+
+    // A short count tells the file ended
+    writer.write_bytes(&[1, 2]).unwrap();
+    assert_eq!(exe.run(1), Ok(StopReason::Blocked(0)));
+    drop(writer);
+    assert_eq!(exe.run(1), Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(0)), Ok(2));
+    assert_eq!(exe.get_val::<u32>(Operand::Loc(2 * W)), Ok(0x0201));
+
+    let functions = [Function {
+        frame_size: 1,
+        program: &[
+            // otv u16 0x0201 n
+            Op::Otv(
+                BinOp::new(Operand::Val(0x0201), Operand::Loc(0)),
+                OpType::U16,
+            ),
+        ],
+    }];
+
+    let (writer, mut reader) = pipe(2);
+    let mut exe = Executor::new(&functions);
+    exe.files.open(writer).unwrap();
+    exe.files.set_current(0).unwrap();
+    exe.call(0, 0).unwrap();
+
+    // No part of the value is written until there is room for all of it
+    exe.files.write(7).unwrap();
+    assert_eq!(exe.run(1), Ok(StopReason::Blocked(0)));
+    assert_eq!(reader.read(), Ok(Some(7)));
+    assert_eq!(reader.read(), Err(FileError::WouldBlock));
+    assert_eq!(exe.run(1), Ok(StopReason::BudgetExhausted));
+    assert_eq!(exe.get_val::<u8>(Operand::Loc(0)), Ok(2));
+
+    let mut buf = [0; 2];
+    assert_eq!(reader.read_bytes(&mut buf), Ok(2));
+    assert_eq!(buf, [1, 2]);
+}
+
+#[test]
+fn executor_pipe() {
+    use crate::executor::pipe;
+
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let writer = [Function {
+        frame_size: 1,
+        program: &[
+            // out i
+            Op::Out(UnOp::new(Operand::Loc(0))),
+            // inc u8 i
+            Op::Inc(UnOp::new(Operand::Loc(0)), OpType::U8),
+            // ifl u8 i 100
+            Op::Ifl(BinOp::new(Operand::Loc(0), Operand::Val(100)), OpType::U8),
+            // go 0
+            Op::Go(Operand::Val(0)),
+            // cls 0
+            Op::Cls(Operand::Val(0)),
+            // end 0
+            Op::End(Operand::Val(0)),
+        ],
+    }];
+
+    let reader = [Function {
+        frame_size: 2 * W,
+        program: &[
+            // in b f
+            Op::In(BinOp::new(Operand::Loc(0), Operand::Loc(1))),
+            // iff u8 f
+            Op::Iff(UnOp::new(Operand::Loc(1)), OpType::U8),
+            // end n
+            Op::End(Operand::Loc(W)),
+            // inc uw n
+            Op::Inc(UnOp::new(Operand::Loc(W)), OpType::Uw),
+            // go 0
+            Op::Go(Operand::Val(0)),
+        ],
+    }];
+
+    let (pipe_writer, pipe_reader) = pipe(8);
+    let pipe = pipe_reader.pipe().clone();
+
+    let mut exe = Executor::new(&writer);
+    exe.files.open(pipe_writer).unwrap();
+    exe.files.set_current(0).unwrap();
+    exe.call(0, 0).unwrap();
+
+    let writer = std::thread::spawn(move || loop {
+        match exe.run(100) {
+            Ok(StopReason::Blocked(_)) => pipe.wait_writable(),
+            Ok(StopReason::BudgetExhausted) => (),
+            stopped => break stopped,
+        }
+    });
+
+    let pipe = pipe_reader.pipe().clone();
+    let mut exe = Executor::new(&reader);
+    exe.files.open(pipe_reader).unwrap();
+    exe.files.set_current(0).unwrap();
+    exe.call(0, 0).unwrap();
+
+    let stopped = loop {
+        match exe.run(100) {
+            Ok(StopReason::Blocked(_)) => pipe.wait_readable(),
+            Ok(StopReason::BudgetExhausted) => (),
+            stopped => break stopped,
+        }
+    };
+
+    assert_eq!(stopped, Ok(StopReason::End(100)));
+    assert_eq!(writer.join().unwrap(), Ok(StopReason::End(0)));
+}
+
+#[test]
+fn executor_shadow_memory() {
+    use std::collections::vec_deque::VecDeque;
+
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let functions = [Function {
+        frame_size: 8,
+        program: &[
+            Op::Set(BinOp::new(Operand::Loc(0), Operand::Val(1)), OpType::U32),
+            Op::Add(BinOp::new(Operand::Loc(0), Operand::Loc(4)), OpType::U32),
+            Op::End(Operand::Loc(0)),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.enable_shadow_memory();
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(
+        exe.run(100),
+        Err(ExecutionError::MemoryError(MemoryError::UninitializedRead(
+            4, 4
+        )))
+    );
+    assert_eq!(exe.program_counter, 1);
+
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+    assert_eq!(exe.run(100), Ok(StopReason::End(1)));
+
+    let functions = [Function {
+        frame_size: 16,
+        program: &[
+            Op::Inb(Operand::Loc(8), Operand::Ref(0), Operand::Val(4)),
+            Op::End(Operand::Loc(8)),
+        ],
+    }];
+
+    // Input is written to unwritten bytes
+    let mut exe = Executor::new(&functions);
+    exe.enable_shadow_memory();
+    exe.files.open(VecDeque::from(vec![1, 2])).unwrap();
+    exe.files.set_current(0).unwrap();
+    exe.call(0, 0).unwrap();
+    assert_eq!(exe.run(100), Ok(StopReason::End(2)));
+    assert_eq!(exe.memory.get::<u16>(0), Ok(0x0201));
+
+    let functions = [
+        Function {
+            frame_size: W,
+            program: &[
+                Op::Slp(Operand::Val(1)),
+                Op::Jon(Operand::Val(1), Operand::Loc(0)),
+                Op::End(Operand::Loc(0)),
+            ],
+        },
+        Function {
+            frame_size: W,
+            program: &[
+                Op::Slp(Operand::Val(5)),
+                Op::App(Operand::Val(2)),
+                Op::Clf(Operand::Val(0)),
+            ],
+        },
+        Function {
+            frame_size: W,
+            program: &[Op::End(Operand::Loc(0))],
+        },
+    ];
+
+    // Stacks of sleeping contexts are tracked too
+    let mut exe = Executor::new(&functions);
+    exe.call(0, 0).unwrap();
+    exe.spawn(1, 0).unwrap();
+    assert_eq!(exe.run(100), Ok(StopReason::Sleep(1)));
+
+    exe.enable_shadow_memory();
+    assert_eq!(exe.run(100), Ok(StopReason::Sleep(4)));
+    assert_eq!(
+        exe.run(100),
+        Err(ExecutionError::MemoryError(MemoryError::UninitializedRead(
+            W, W
+        )))
+    );
+}
+
+#[test]
+fn executor_heap_sanitizer() {
+    use crate::executor::{Allocation, HeapAccess};
+
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let functions = [Function {
+        frame_size: W * 2,
+        program: &[
+            Op::Alc(Operand::Loc(W), Operand::Val(4)),
+            Op::Set(BinOp::new(Operand::Ind(W), Operand::Val(1)), OpType::U32),
+            Op::Fre(Operand::Loc(W)),
+            Op::Set(BinOp::new(Operand::Ind(W), Operand::Val(2)), OpType::U32),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.enable_heap_sanitizer(W, 64);
+    exe.call(0, 0).unwrap();
+    assert_eq!(exe.run(3), Ok(StopReason::BudgetExhausted));
+
+    let snapshot = exe.snapshot();
+    let mut restored = Executor::new(&functions);
+    restored.restore(&snapshot, |_, _| None).unwrap();
+
+    let ptr = Memory::HEAP_BASE + W;
+    let site = |program_counter| {
+        Some(Location {
+            function_id: 0,
+            program_counter,
+        })
+    };
+
+    assert_eq!(
+        restored.run(1),
+        Err(ExecutionError::MemoryError(MemoryError::UseAfterFree(
+            HeapAccess {
+                ptr,
+                size: 4,
+                allocation: Some(Allocation {
+                    ptr,
+                    size: 4,
+                    allocated: site(0),
+                    freed: site(2),
+                }),
+            }
+        )))
+    );
+}
+
+#[test]
+fn executor_devices() {
+    use crate::executor::Device;
+    use std::any::Any;
+
+    #[derive(Debug)]
+    struct Timer(u32);
+
+    impl Device for Timer {
+        fn size(&self) -> UWord {
+            4
+        }
+
+        fn read(&self, offset: UWord, buf: &mut [u8]) {
+            let offset = offset as usize;
+            buf.copy_from_slice(&self.0.to_le_bytes()[offset..offset + buf.len()]);
+        }
+
+        fn write(&mut self, _: UWord, _: &[u8]) {}
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    const SCREEN: UWord = 4096;
+    const TIMER: UWord = 4200;
+
+    let functions = [Function {
+        frame_size: 8,
+        program: &[
+            Op::Set(
+                BinOp::new(Operand::Glb(SCREEN), Operand::Val(7)),
+                OpType::U8,
+            ),
+            Op::Add(
+                BinOp::new(Operand::Glb(SCREEN), Operand::Val(1)),
+                OpType::U8,
+            ),
+            Op::Cpy(
+                Operand::Val(SCREEN + 1),
+                Operand::Val(SCREEN),
+                Operand::Val(1),
+            ),
+            Op::Set(
+                BinOp::new(Operand::Loc(0), Operand::Glb(TIMER)),
+                OpType::U32,
+            ),
+            Op::End(Operand::Loc(0)),
+        ],
+    }];
+
+    let mut exe = Executor::new(&functions);
+    exe.map_device(SCREEN, vec![0; 4]).unwrap();
+    exe.map_device(TIMER, Timer(42)).unwrap();
+    exe.call(0, 0).unwrap();
+
+    assert_eq!(exe.run(100), Ok(StopReason::End(42)));
+
+    let screen = exe.unmap_device(SCREEN).unwrap();
+    assert_eq!(
+        screen.as_any().downcast_ref::<Vec<u8>>().unwrap(),
+        &[8, 8, 0, 0]
+    );
+}
+
+#[test]
+fn executor_paged_memory() {
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+    const PAGE: UWord = MemoryPage::PAGE_SIZE;
+    const SIZE: UWord = 8 << 20;
+
+    let functions = [Function {
+        frame_size: W * 2,
+        program: &[
+            Op::Alc(Operand::Loc(W), Operand::Val(SIZE)),
+            Op::Set(
+                BinOp::new(Operand::Glb(Memory::HEAP_BASE + SIZE - 4), Operand::Val(1)),
+                OpType::U32,
+            ),
+            Op::Set(
+                BinOp::new(Operand::Glb(Memory::HEAP_BASE + PAGE), Operand::Val(2)),
+                OpType::U32,
+            ),
+        ],
+    }];
+
+    let limits = Limits {
+        heap: 16 << 20,
+        paged: true,
+        ..Limits::default()
+    };
+
+    let mut exe = Executor::with_limits(&functions, limits);
+    exe.protect(Memory::HEAP_BASE + PAGE, PAGE, Permission::Read)
+        .unwrap();
+    exe.call(0, 0).unwrap();
+    assert_eq!(exe.run(2), Ok(StopReason::BudgetExhausted));
+
+    // Only written pages are saved
+    let snapshot = exe.snapshot();
+    assert!(snapshot.len() < PAGE as usize * 2);
+
+    let mut restored = Executor::with_limits(&functions, limits);
+    restored.restore(&snapshot, |_, _| None).unwrap();
+    assert_eq!(
+        restored.memory.get::<u32>(Memory::HEAP_BASE + SIZE - 4),
+        Ok(1)
+    );
+    assert_eq!(
+        restored.run(1),
+        Err(ExecutionError::MemoryError(MemoryError::AccessViolation(
+            PAGE, 4
+        )))
+    );
+
+    let functions = [
+        Function {
+            frame_size: W,
+            program: &[
+                Op::Spw(Operand::Loc(0), Operand::Val(1), Operand::Val(0)),
+                Op::Jon(Operand::Loc(0), Operand::Loc(0)),
+                Op::End(Operand::Loc(0)),
+            ],
+        },
+        Function {
+            frame_size: PAGE * 2,
+            program: &[
+                Op::Set(BinOp::new(Operand::Loc(PAGE), Operand::Val(1)), OpType::U32),
+                Op::End(Operand::Val(0)),
+            ],
+        },
+    ];
+
+    let limits = Limits {
+        stack: PAGE as usize * 4,
+        ..limits
+    };
+
+    // Stacks of spawned contexts keep the guard page
+    let mut exe = Executor::with_limits(&functions, limits);
+    exe.protect(PAGE, PAGE, Permission::None).unwrap();
+    exe.call(0, 0).unwrap();
+    assert_eq!(
+        exe.run(100),
+        Err(ExecutionError::MemoryError(MemoryError::AccessViolation(
+            PAGE, 4
+        )))
+    );
+    assert_eq!(exe.context(), 1);
+
+    let functions = [Function {
+        frame_size: PAGE - 4,
+        program: &[
+            Op::App(Operand::Val(1)),
+            Op::Par(UnOp::new(Operand::Val(2)), OpType::U32),
+            Op::Par(UnOp::new(Operand::Val(3)), OpType::U32),
+            Op::Clf(Operand::Ref(0)),
+            Op::End(Operand::Loc(0)),
+        ],
+    }];
+
+    // Parameters are read across pages
+    let mut exe = Executor::with_limits(&functions, limits);
+    exe.register(1, 8, |call| {
+        let parameters = call.parameters()?;
+        let a = u32::from_le_bytes([parameters[0], parameters[1], parameters[2], parameters[3]]);
+        let b = u32::from_le_bytes([parameters[4], parameters[5], parameters[6], parameters[7]]);
+        call.ret(a + b)?;
+        Ok(())
+    });
+    exe.call(0, 0).unwrap();
+    assert_eq!(exe.run(100), Ok(StopReason::End(5)));
+}
+
+#[test]
+fn executor_program() {
+    const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+    let mut program = Program::new();
+    let id = program.push(
+        W,
+        vec![
+            Op::Add(BinOp::new(Operand::Loc(0), Operand::Val(7)), OpType::Uw),
+            Op::End(Operand::Loc(0)),
+        ],
+    );
+    assert_eq!(id, 0);
+    assert_eq!(program.len(), 1);
+    assert_eq!(program.function(0).map(|f| f.frame_size()), Some(W));
+    assert!(program.function(1).is_none());
+
+    fn assert_send<T: Send + 'static>(_: &T) {}
+
+    let handles: Vec<_> = (0..2)
+        .map(|_| {
+            let mut exe = Executor::from_shared(&program);
+            assert_send(&exe);
+
+            std::thread::spawn(move || {
+                exe.call(0, 0).unwrap();
+                exe.run(100)
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), Stopped::Ok(StopReason::End(7)));
+    }
+
+    let functions = [Function::new(W, &[Op::End(Operand::Val(1))])];
+    let program = Program::from_functions(&functions);
+    let mut exe = Executor::from_program(program, Limits::default());
+    exe.call(0, 0).unwrap();
+    assert_eq!(exe.run(100), Stopped::Ok(StopReason::End(1)));
+}
+
+#[test]
+fn executor_compiled() {
+    let functions = [
+        Function {
+            frame_size: 16,
+            program: &[
+                // i = 0; sum = 0; loop: sum += i * 3; i += 1; if i < 10 go loop
+                Op::Set(BinOp::new(Operand::Loc(0), Operand::Val(0)), OpType::U32),
+                Op::Set(BinOp::new(Operand::Loc(4), Operand::Val(0)), OpType::U32),
+                Op::Set(BinOp::new(Operand::Loc(8), Operand::Loc(0)), OpType::U32),
+                Op::Mul(BinOp::new(Operand::Loc(8), Operand::Val(3)), OpType::U32),
+                Op::Add(BinOp::new(Operand::Loc(4), Operand::Loc(8)), OpType::U32),
+                Op::Inc(UnOp::new(Operand::Loc(0)), OpType::U32),
+                Op::Ifl(BinOp::new(Operand::Loc(0), Operand::Val(10)), OpType::U32),
+                Op::Go(Operand::Val(2)),
+                Op::Shl(Operand::Loc(4), Operand::Val(1), OpType::U16),
+                Op::Ine(BinOp::new(Operand::Loc(4), Operand::Val(0)), OpType::F32),
+                Op::Nop,
+                Op::Sbs(BinOp::new(Operand::Loc(12), Operand::Val(1)), OpType::U8),
+                Op::Adc(BinOp::new(Operand::Loc(12), Operand::Val(1)), OpType::I8),
+                Op::App(Operand::Val(1)),
+                Op::Par(UnOp::new(Operand::Loc(4)), OpType::U32),
+                Op::Clf(Operand::Ref(8)),
+                Op::Xor(BinOp::new(Operand::Loc(8), Operand::Val(1)), OpType::F64),
+            ],
+        },
+        Function {
+            frame_size: 4,
+            program: &[
+                Op::Dec(UnOp::new(Operand::Loc(0)), OpType::U32),
+                Op::Ret(UnOp::new(Operand::Loc(0)), OpType::U32),
+            ],
+        },
+    ];
+
+    let trace = |compile| {
+        let mut exe = Executor::new(&functions);
+        exe.call(0, 0).unwrap();
+
+        if compile {
+            exe.compile();
+        }
+
+        let mut results = Vec::new();
+
+        loop {
+            let executed = exe.execute();
+            results.push((executed, exe.program_counter, exe.call_depth()));
+
+            if executed.is_err() {
+                break;
+            }
+        }
+
+        (results, exe.memory.stack.get(0, 16).unwrap().to_vec())
+    };
+
+    let (interpreted, stack) = trace(false);
+    assert_eq!(
+        interpreted.last().unwrap().0,
+        Executed::Err(ExecutionError::IncorrectOperation(functions[0].program[16]))
+    );
+    assert_eq!(trace(true), (interpreted, stack));
+}
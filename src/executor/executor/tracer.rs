@@ -0,0 +1,193 @@
+use super::{Executed, ExecutionError, Executor, Location, Primary};
+use crate::common::*;
+
+/// Operand of a traced operation.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TracedOperand {
+    /// The operand with the offset applied.
+    pub operand: Operand,
+
+    /// Size of the operand value in bytes.
+    pub size: UWord,
+
+    /// Address of the operand in memory, if it refers to memory.
+    pub address: Option<UWord>,
+
+    /// Little-endian bits of the operand value, if it is readable.
+    pub value: Option<u64>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Trace {
+    pub location: Location,
+    pub op: Op,
+    pub operands: [Option<TracedOperand>; 3],
+}
+
+/// Observer of executed operations.
+pub trait Tracer: std::fmt::Debug + Send {
+    fn before(&mut self, trace: &Trace);
+
+    /// Called after the operation. Values of the operands are read again.
+    fn after(&mut self, trace: &Trace, executed: &Executed) {
+        let _ = (trace, executed);
+    }
+}
+
+impl Executor {
+    pub fn set_tracer<T>(&mut self, tracer: T)
+    where
+        T: Tracer + 'static,
+    {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    pub(super) fn execute_traced(&mut self, mut tracer: Box<dyn Tracer>) -> Executed {
+        let mut trace = match self.trace() {
+            Some(trace) => trace,
+            None => {
+                self.tracer = Some(tracer);
+                return self.execute_op();
+            }
+        };
+
+        tracer.before(&trace);
+        let executed = self.execute_op();
+
+        for operand in trace.operands.iter_mut().flatten() {
+            if let Some(address) = operand.address {
+                operand.value = self.read_bits(address, operand.size);
+            }
+        }
+
+        tracer.after(&trace, &executed);
+        self.tracer = Some(tracer);
+
+        executed
+    }
+
+    fn trace(&self) -> Option<Trace> {
+        let location = self.location()?;
+        let op = *self.current_op().ok()?;
+
+        Some(Trace {
+            location,
+            op,
+            operands: self.trace_operands(op),
+        })
+    }
+
+    fn trace_operands(&self, op: Op) -> [Option<TracedOperand>; 3] {
+        use Op::*;
+
+        const W: UWord = std::mem::size_of::<UWord>() as UWord;
+
+        let bin = |bin: BinOp, size: UWord| match self.read_bin_operands(bin) {
+            Ok((x, y)) => [Some((x, size)), Some((y, size)), None],
+            Err(_) => [None; 3],
+        };
+
+        let un = |un: UnOp, size: UWord| match self.read_un_operand(un) {
+            Ok(x) => [Some((x, size)), None, None],
+            Err(_) => [None; 3],
+        };
+
+        let operands = match op {
+            Nop | Fls => [None; 3],
+            End(x) | Slp(x) | Go(x) | App(x) | Clf(x) | Sfd(x) | Gfd(x) | Cls(x) | Fre(x) => {
+                [Some((x, W)), None, None]
+            }
+            Set(b, t)
+            | Add(b, t)
+            | Sub(b, t)
+            | Mul(b, t)
+            | Adc(b, t)
+            | Sbc(b, t)
+            | Mlc(b, t)
+            | Ads(b, t)
+            | Sbs(b, t)
+            | Mls(b, t)
+            | Div(b, t)
+            | Mod(b, t)
+            | And(b, t)
+            | Or(b, t)
+            | Xor(b, t)
+            | Ife(b, t)
+            | Ifl(b, t)
+            | Ifg(b, t)
+            | Ine(b, t)
+            | Inl(b, t)
+            | Ing(b, t)
+            | Ifa(b, t)
+            | Ifo(b, t)
+            | Ifx(b, t)
+            | Ina(b, t)
+            | Ino(b, t)
+            | Inx(b, t)
+            | Inv(b, t)
+            | Otv(b, t) => bin(b, t.size()),
+            Cnv(x, y, t, u) => [Some((x, t.size())), Some((y, u.size())), None],
+            Shl(x, y, t) | Shr(x, y, t) => [Some((x, t.size())), Some((y, 1)), None],
+            Not(u, t)
+            | Neg(u, t)
+            | Inc(u, t)
+            | Dec(u, t)
+            | Ift(u, t)
+            | Iff(u, t)
+            | Par(u, t)
+            | Ret(u, t) => un(u, t.size()),
+            In(b) => bin(b, 1),
+            Out(u) => un(u, 1),
+            Zer(x, y) | Giv(x, y) | Alc(x, y) | Jon(x, y) => [Some((x, W)), Some((y, W)), None],
+            Opn(x, y, z)
+            | Cmp(x, y, z)
+            | Cpy(x, y, z)
+            | Spw(x, y, z)
+            | Inb(x, y, z)
+            | Otb(x, y, z) => [Some((x, W)), Some((y, W)), Some((z, W))],
+        };
+
+        operands.map(|o| o.map(|(operand, size)| self.trace_operand(operand, size)))
+    }
+
+    fn trace_operand(&self, operand: Operand, size: UWord) -> TracedOperand {
+        let address = self.operand_address(operand).ok().flatten();
+
+        let value = match (operand, address) {
+            (_, Some(address)) => self.read_bits(address, size),
+            (Operand::Val(_), _) | (Operand::Ref(_), _) => self.get_val::<u64>(operand).ok(),
+            _ => None,
+        };
+
+        TracedOperand {
+            operand,
+            size,
+            address,
+            value,
+        }
+    }
+
+    fn operand_address(&self, operand: Operand) -> Result<Option<UWord>, ExecutionError> {
+        Ok(match operand {
+            Operand::Loc(loc) => Some(self.current_call()?.base_ptr.wrapping_add(loc)),
+            Operand::Ind(0) => None,
+            Operand::Ind(ptr) => Some(
+                self.memory
+                    .get(self.current_call()?.base_ptr.wrapping_add(ptr))?,
+            ),
+            Operand::Ret(ret) => Some(self.current_call()?.ret_val_ptr.wrapping_add(ret)),
+            Operand::Glb(ptr) => Some(ptr),
+            Operand::Val(_) | Operand::Ref(_) | Operand::Emp => None,
+        })
+    }
+
+    fn read_bits(&self, address: UWord, size: UWord) -> Option<u64> {
+        let size = size.min(std::mem::size_of::<u64>() as UWord);
+        let bytes = self.memory.read(address, size).ok()?;
+        Some(u64::from_slice(&bytes))
+    }
+}
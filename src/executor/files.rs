@@ -1,14 +1,21 @@
 use std::{any::Any, collections::vec_deque::VecDeque};
 
+use super::{
+    io::Descriptor,
+    snapshot::{Reader, SnapshotError, Writer},
+};
 use crate::common::UWord;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum FileError {
     ReadingNotAvailable,
     WritingNotAvailable,
+    /// The file isn't ready yet, the operation should be repeated later.
+    WouldBlock,
+    Io(std::io::ErrorKind),
 }
 
-pub trait File: std::fmt::Debug {
+pub trait File: std::fmt::Debug + Send {
     fn read(&mut self) -> Result<Option<u8>, FileError>;
 
     fn write(&mut self, val: u8) -> Result<(), FileError>;
@@ -17,7 +24,96 @@ pub trait File: std::fmt::Debug {
         Ok(())
     }
 
+    /// Reads bytes into `buf` and returns the number of bytes read, which is less
+    /// than the length of `buf` at the end of the file. The error is returned
+    /// only if no bytes were read.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, FileError> {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            match self.read() {
+                Ok(Some(val)) => *byte = val,
+                Ok(None) => return Ok(i),
+                Err(e) if i == 0 => return Err(e),
+                Err(_) => return Ok(i),
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Writes bytes of `buf` and returns the number of bytes written.
+    /// The error is returned only if no bytes were written.
+    fn write_bytes(&mut self, buf: &[u8]) -> Result<usize, FileError> {
+        for (i, &val) in buf.iter().enumerate() {
+            match self.write(val) {
+                Ok(()) => (),
+                Err(e) if i == 0 => return Err(e),
+                Err(_) => return Ok(i),
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Reads all bytes of a value into `buf` at once. If not all of them are
+    /// available, the error `WouldBlock` is returned and nothing is read. The
+    /// number of bytes read is less than the length of `buf` only at the end of
+    /// the file.
+    ///
+    /// By default it reads with `read_bytes` until `buf` is full, so files which
+    /// may block after a part of the value should override it.
+    fn read_value(&mut self, buf: &mut [u8]) -> Result<usize, FileError> {
+        let mut len = 0;
+
+        while len < buf.len() {
+            match self.read_bytes(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if len == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+
+        Ok(len)
+    }
+
+    /// Writes all bytes of a value at once. If there is no room for all of them,
+    /// the error `WouldBlock` is returned and nothing is written.
+    ///
+    /// By default it writes with `write_bytes` until `buf` is written, so files
+    /// which may block after a part of the value should override it.
+    fn write_value(&mut self, buf: &[u8]) -> Result<usize, FileError> {
+        let mut len = 0;
+
+        while len < buf.len() {
+            match self.write_bytes(&buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if len == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+
+        Ok(len)
+    }
+
+    /// Checks that the operation which would block can be repeated now. Executors
+    /// blocked on the file are woken by the `Scheduler` when it's ready. Files that
+    /// can't tell are never ready, executors blocked on them are woken by the host.
+    fn is_ready(&self) -> bool {
+        false
+    }
+
     fn as_any(&self) -> &dyn Any;
+
+    /// Returns the file as a `FileSnapshot` if its state can be saved.
+    fn as_snapshot(&self) -> Option<&dyn FileSnapshot> {
+        None
+    }
+}
+
+/// File whose state can be saved into an executor snapshot.
+pub trait FileSnapshot {
+    fn save(&self) -> Vec<u8>;
 }
 
 impl File for Vec<u8> {
@@ -33,6 +129,16 @@ impl File for Vec<u8> {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_snapshot(&self) -> Option<&dyn FileSnapshot> {
+        Some(self)
+    }
+}
+
+impl FileSnapshot for Vec<u8> {
+    fn save(&self) -> Vec<u8> {
+        self.clone()
+    }
 }
 
 impl File for VecDeque<u8> {
@@ -49,6 +155,21 @@ impl File for VecDeque<u8> {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn as_snapshot(&self) -> Option<&dyn FileSnapshot> {
+        Some(self)
+    }
+}
+
+impl FileSnapshot for VecDeque<u8> {
+    fn save(&self) -> Vec<u8> {
+        self.iter().copied().collect()
+    }
+}
+
+/// Resolves file names requested by a program into files.
+pub trait FileResolver: std::fmt::Debug + Send {
+    fn resolve(&mut self, name: &[u8]) -> Option<Box<dyn File>>;
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -56,7 +177,9 @@ pub enum FilesError {
     FileError(FileError),
     CurrentIsNotSet,
     LimitExceeded,
+    WriteLimitExceeded,
     NotFound,
+    ResolverIsNotSet,
 }
 
 impl From<FileError> for FilesError {
@@ -70,27 +193,75 @@ pub struct Files {
     files: Vec<Option<Box<dyn File>>>,
     count: usize,
     current: Option<(usize, Box<dyn File>)>,
+    resolver: Option<Box<dyn FileResolver>>,
+    limit: usize,
+    written: u64,
+    write_limit: Option<u64>,
 }
 
 impl Files {
-    pub const LIMIT: usize = 64;
+    pub const DEFAULT_LIMIT: usize = 64;
 
     pub fn new() -> Self {
+        Self::from_limits(Self::DEFAULT_LIMIT, None)
+    }
+
+    /// Creates files with the limit of open files and the limit of bytes written by `write`.
+    pub fn from_limits(limit: usize, write_limit: Option<u64>) -> Self {
         Self {
             files: Vec::new(),
             count: 0,
             current: None,
+            resolver: None,
+            limit,
+            written: 0,
+            write_limit,
         }
     }
 
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Number of bytes written by `write`.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn set_resolver<R>(&mut self, resolver: R)
+    where
+        R: FileResolver + 'static,
+    {
+        self.resolver = Some(Box::new(resolver));
+    }
+
     pub fn open<F>(&mut self, file: F) -> Result<UWord, (FilesError, F)>
     where
         F: File + 'static,
     {
-        if self.count == Self::LIMIT {
+        if self.count >= self.limit {
             return Err((FilesError::LimitExceeded, file));
         }
 
+        Ok(self.insert(Box::new(file)))
+    }
+
+    pub fn open_named(&mut self, name: &[u8]) -> Result<UWord, FilesError> {
+        if self.count >= self.limit {
+            return Err(FilesError::LimitExceeded);
+        }
+
+        let file = self
+            .resolver
+            .as_mut()
+            .ok_or(FilesError::ResolverIsNotSet)?
+            .resolve(name)
+            .ok_or(FilesError::NotFound)?;
+
+        Ok(self.insert(file))
+    }
+
+    fn insert(&mut self, file: Box<dyn File>) -> UWord {
         let mut idx = None;
         let current = self.current.as_ref().map(|(idx, _)| *idx);
 
@@ -115,10 +286,10 @@ impl Files {
             len
         });
 
-        self.files[idx] = Some(Box::new(file));
+        self.files[idx] = Some(file);
         self.count += 1;
 
-        Ok(idx as UWord)
+        idx as UWord
     }
 
     pub fn close(&mut self, idx: UWord) -> Result<Box<dyn File>, FilesError> {
@@ -138,8 +309,14 @@ impl Files {
         Ok(file)
     }
 
+    /// Makes the file current. The previous current file is returned to its cell.
     pub fn set_current(&mut self, idx: UWord) -> Result<(), FilesError> {
         let idx = idx as usize;
+
+        if matches!(self.current, Some((current, _)) if current == idx) {
+            return Ok(());
+        }
+
         let file = self
             .files
             .get_mut(idx)
@@ -147,7 +324,10 @@ impl Files {
             .take()
             .ok_or(FilesError::NotFound)?;
 
-        self.current = Some((idx, file));
+        if let Some((current, file)) = self.current.replace((idx, file)) {
+            self.files[current] = Some(file);
+        }
+
         Ok(())
     }
 
@@ -157,6 +337,34 @@ impl Files {
         Ok(*current as UWord)
     }
 
+    /// Checks that the file of the descriptor is ready, see `File::is_ready`.
+    pub fn is_ready(&self, idx: UWord) -> bool {
+        let file = match &self.current {
+            Some((current, file)) if *current == idx as usize => Some(file),
+            _ => self.files.get(idx as usize).and_then(Option::as_ref),
+        };
+
+        file.is_some_and(|file| file.is_ready())
+    }
+
+    /// Returns the file of the descriptor as `std::io::Read` and `std::io::Write`.
+    pub fn descriptor(&mut self, idx: UWord) -> Result<Descriptor<'_>, FilesError> {
+        self.file_mut(idx).map(Descriptor::new)
+    }
+
+    fn file_mut(&mut self, idx: UWord) -> Result<&mut dyn File, FilesError> {
+        let file = match &mut self.current {
+            Some((current, file)) if *current == idx as usize => file,
+            _ => self
+                .files
+                .get_mut(idx as usize)
+                .and_then(Option::as_mut)
+                .ok_or(FilesError::NotFound)?,
+        };
+
+        Ok(Box::as_mut(file) as &mut dyn File)
+    }
+
     fn get_mut(&mut self) -> Result<&mut dyn File, FilesError> {
         let (_, file) = self.current.as_mut().ok_or(FilesError::CurrentIsNotSet)?;
 
@@ -170,16 +378,178 @@ impl Files {
     }
 
     pub fn write(&mut self, val: u8) -> Result<(), FilesError> {
+        if self.write_limit.is_some_and(|limit| self.written >= limit) {
+            return Err(FilesError::WriteLimitExceeded);
+        }
+
         let file = self.get_mut()?;
         file.write(val)?;
+        self.written += 1;
         Ok(())
     }
 
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, FilesError> {
+        let file = self.get_mut()?;
+        let len = file.read_bytes(buf)?;
+        Ok(len)
+    }
+
+    pub fn read_value(&mut self, buf: &mut [u8]) -> Result<usize, FilesError> {
+        let file = self.get_mut()?;
+        let len = file.read_value(buf)?;
+        Ok(len)
+    }
+
+    /// Writes all bytes of the value, which fails if they exceed the write limit.
+    pub fn write_value(&mut self, buf: &[u8]) -> Result<usize, FilesError> {
+        if self
+            .write_limit
+            .is_some_and(|limit| self.written.saturating_add(buf.len() as u64) > limit)
+        {
+            return Err(FilesError::WriteLimitExceeded);
+        }
+
+        let file = self.get_mut()?;
+        let len = file.write_value(buf)?;
+        self.written += len as u64;
+        Ok(len)
+    }
+
+    /// Writes bytes of `buf` up to the write limit and returns the number of bytes written.
+    pub fn write_bytes(&mut self, buf: &[u8]) -> Result<usize, FilesError> {
+        let buf = match self.write_limit {
+            Some(limit) if self.written >= limit && !buf.is_empty() => {
+                return Err(FilesError::WriteLimitExceeded)
+            }
+            Some(limit) => {
+                &buf[..limit.saturating_sub(self.written).min(buf.len() as u64) as usize]
+            }
+            None => buf,
+        };
+
+        let file = self.get_mut()?;
+        let len = file.write_bytes(buf)?;
+        self.written += len as u64;
+        Ok(len)
+    }
+
     pub fn flush(&mut self) -> Result<(), FilesError> {
         let file = self.get_mut()?;
         file.flush()?;
         Ok(())
     }
+
+    /// Saves the descriptor table. The state of files which don't implement
+    /// `FileSnapshot` is not saved.
+    pub(super) fn save(&self, w: &mut Writer) {
+        let current = self.current.as_ref().map(|(idx, file)| (*idx, file));
+        let files = self
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, file)| match (file, current) {
+                (Some(file), _) => Some((idx, file, false)),
+                (None, Some((c, file))) if c == idx => Some((idx, file, true)),
+                (None, _) => None,
+            })
+            .collect::<Vec<_>>();
+
+        w.word(self.files.len() as UWord);
+        w.word(files.len() as UWord);
+
+        w.u64(self.written);
+
+        for (idx, file, is_current) in files {
+            w.word(idx as UWord);
+            w.bool(is_current);
+
+            match file.as_snapshot() {
+                Some(file) => {
+                    w.bool(true);
+                    w.bytes(&file.save());
+                }
+                None => w.bool(false),
+            }
+        }
+    }
+
+    /// Loads the descriptor table. Every file is restored by the `loader`
+    /// from its descriptor and saved state.
+    pub(super) fn load<L>(&self, r: &mut Reader, mut loader: L) -> Result<Self, SnapshotError>
+    where
+        L: FnMut(UWord, Option<&[u8]>) -> Option<Box<dyn File>>,
+    {
+        let len = r.word()? as usize;
+        let count = r.word()? as usize;
+
+        if count > len {
+            return Err(SnapshotError::InvalidData);
+        }
+
+        if count > self.limit {
+            return Err(FilesError::LimitExceeded.into());
+        }
+
+        // The table never grows over the limit, so the length is checked before allocating
+        if len > self.limit {
+            return Err(SnapshotError::InvalidData);
+        }
+
+        let written = r.u64()?;
+
+        let mut files: Vec<Option<Box<dyn File>>> = Vec::new();
+        files.resize_with(len, || None);
+        let mut current = None;
+
+        for _ in 0..count {
+            let idx = r.word()?;
+            let is_current = r.bool()?;
+            let state = if r.bool()? { Some(r.bytes()?) } else { None };
+
+            let is_taken = current.as_ref().is_some_and(|(c, _)| *c == idx as usize);
+            let cell = files
+                .get_mut(idx as usize)
+                .filter(|cell| cell.is_none() && !is_taken)
+                .ok_or(SnapshotError::InvalidData)?;
+
+            let file = loader(idx, state).ok_or(SnapshotError::FileNotRestored(idx))?;
+
+            if is_current {
+                if current.is_some() {
+                    return Err(SnapshotError::InvalidData);
+                }
+
+                current = Some((idx as usize, file));
+            } else {
+                *cell = Some(file);
+            }
+        }
+
+        let mut loaded = Self::from_limits(self.limit, self.write_limit);
+        loaded.files = files;
+        loaded.count = count;
+        loaded.current = current;
+        loaded.written = match self.write_limit {
+            Some(limit) => written.min(limit),
+            None => written,
+        };
+
+        Ok(loaded)
+    }
+
+    /// Replaces the descriptor table with the table of `files`, keeping the resolver.
+    pub(super) fn replace(&mut self, files: Files) {
+        self.files = files.files;
+        self.count = files.count;
+        self.current = files.current;
+        self.written = files.written;
+    }
+}
+
+impl Default for Files {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
@@ -200,4 +570,114 @@ mod tests {
         assert_eq!(files.current(), Err(FilesError::CurrentIsNotSet));
         assert_eq!(files.open(Vec::new()), Ok(0));
     }
+
+    #[test]
+    fn files_set_current() {
+        use crate::executor::pipe;
+
+        let (writer, mut reader) = pipe(4);
+        let mut files = Files::new();
+        files.open(writer).unwrap();
+        files.open(Vec::new()).unwrap();
+
+        files.set_current(0).unwrap();
+        files.write(1).unwrap();
+        files.set_current(1).unwrap();
+        files.write(2).unwrap();
+        files.set_current(0).unwrap();
+        files.set_current(0).unwrap();
+        files.write(3).unwrap();
+        assert_eq!(files.set_current(2), Err(FilesError::NotFound));
+        assert_eq!(files.current(), Ok(0));
+
+        let mut buf = [0; 4];
+        assert_eq!(reader.read_bytes(&mut buf), Ok(2));
+        assert_eq!(buf[..2], [1, 3]);
+
+        let output = files.close(1).unwrap();
+        assert_eq!(output.as_any().downcast_ref::<Vec<u8>>().unwrap(), &[2]);
+    }
+
+    #[test]
+    fn files_open_named() {
+        #[derive(Debug)]
+        struct Resolver;
+
+        impl FileResolver for Resolver {
+            fn resolve(&mut self, name: &[u8]) -> Option<Box<dyn File>> {
+                match name {
+                    b"out" => Some(Box::new(Vec::new())),
+                    _ => None,
+                }
+            }
+        }
+
+        let mut files = Files::new();
+        assert_eq!(files.open_named(b"out"), Err(FilesError::ResolverIsNotSet));
+
+        files.set_resolver(Resolver);
+        assert_eq!(files.open_named(b"out"), Ok(0));
+        assert_eq!(files.open_named(b"in"), Err(FilesError::NotFound));
+    }
+
+    #[test]
+    fn files_snapshot() {
+        let mut files = Files::new();
+        files.open(Vec::new()).unwrap();
+        files.open(Vec::new()).unwrap();
+        files.set_current(0).unwrap();
+        files.set_current(1).unwrap();
+        files.write(5).unwrap();
+
+        let mut w = Writer::new();
+        files.save(&mut w);
+        let buf = w.finish();
+
+        let mut r = Reader::new(&buf).unwrap();
+        let loaded = files
+            .load(&mut r, |_, _| Some(Box::new(Vec::new())))
+            .unwrap();
+        assert_eq!(r.finish(), Ok(()));
+        assert_eq!(loaded.current(), Ok(1));
+        assert_eq!(loaded.written(), 1);
+
+        // The written bytes don't exceed the write limit of the loading files
+        let mut r = Reader::new(&buf).unwrap();
+        let limited = Files::from_limits(Files::DEFAULT_LIMIT, Some(0))
+            .load(&mut r, |_, _| Some(Box::new(Vec::new())))
+            .unwrap();
+        assert_eq!(limited.written(), 0);
+
+        let mut w = Writer::new();
+        w.word(UWord::MAX);
+        w.word(0);
+        let buf = w.finish();
+
+        let mut r = Reader::new(&buf).unwrap();
+        assert_eq!(
+            files.load(&mut r, |_, _| None).err(),
+            Some(SnapshotError::InvalidData)
+        );
+    }
+
+    #[test]
+    fn files_write_bytes() {
+        let mut files = Files::from_limits(Files::DEFAULT_LIMIT, Some(3));
+        files.open(Vec::new()).unwrap();
+        files.set_current(0).unwrap();
+
+        assert_eq!(files.write_bytes(&[1, 2]), Ok(2));
+        assert_eq!(files.write_bytes(&[3, 4]), Ok(1));
+        assert_eq!(files.write_bytes(&[5]), Err(FilesError::WriteLimitExceeded));
+        assert_eq!(files.written(), 3);
+
+        files.written = 4;
+        assert_eq!(files.write_bytes(&[]), Ok(0));
+
+        let mut buf = [0; 4];
+        assert_eq!(
+            files.read_bytes(&mut buf),
+            Err(FilesError::FileError(FileError::ReadingNotAvailable))
+        );
+    }
 }
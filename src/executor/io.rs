@@ -0,0 +1,307 @@
+use super::files::{File, FileError, FilesError};
+use std::{any::Any, io};
+
+fn file_error(e: io::Error) -> FileError {
+    match e.kind() {
+        io::ErrorKind::WouldBlock => FileError::WouldBlock,
+        kind => FileError::Io(kind),
+    }
+}
+
+fn io_error(e: FilesError) -> io::Error {
+    let kind = match e {
+        FilesError::FileError(FileError::WouldBlock) => io::ErrorKind::WouldBlock,
+        FilesError::FileError(FileError::Io(kind)) => kind,
+        FilesError::FileError(_) => io::ErrorKind::Unsupported,
+        FilesError::NotFound => io::ErrorKind::NotFound,
+        _ => io::ErrorKind::Other,
+    };
+
+    io::Error::new(kind, format!("{:?}", e))
+}
+
+fn read_byte<R>(reader: &mut R) -> Result<Option<u8>, FileError>
+where
+    R: io::Read,
+{
+    let mut buf = [0];
+
+    loop {
+        return match reader.read(&mut buf) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(buf[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(file_error(e)),
+        };
+    }
+}
+
+fn read_into<R>(reader: &mut R, buf: &mut [u8]) -> Result<usize, FileError>
+where
+    R: io::Read,
+{
+    loop {
+        return match reader.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => res.map_err(file_error),
+        };
+    }
+}
+
+fn write_from<W>(writer: &mut W, buf: &[u8]) -> Result<usize, FileError>
+where
+    W: io::Write,
+{
+    loop {
+        return match writer.write(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => res.map_err(file_error),
+        };
+    }
+}
+
+fn write_byte<W>(writer: &mut W, val: u8) -> Result<(), FileError>
+where
+    W: io::Write,
+{
+    writer.write_all(&[val]).map_err(file_error)
+}
+
+/// Readable file over `std::io::Read`.
+///
+/// Every operation reads one byte, so slow readers should be wrapped in `std::io::BufReader`.
+#[derive(Debug)]
+pub struct ReadFile<R>(R);
+
+impl<R> ReadFile<R> {
+    pub fn new(reader: R) -> Self {
+        Self(reader)
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.0
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.0
+    }
+
+    pub fn into_inner(self) -> R {
+        self.0
+    }
+}
+
+impl<R> File for ReadFile<R>
+where
+    R: io::Read + std::fmt::Debug + Send + 'static,
+{
+    fn read(&mut self) -> Result<Option<u8>, FileError> {
+        read_byte(&mut self.0)
+    }
+
+    fn write(&mut self, _: u8) -> Result<(), FileError> {
+        Err(FileError::WritingNotAvailable)
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, FileError> {
+        read_into(&mut self.0, buf)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Writable file over `std::io::Write`.
+///
+/// Every operation writes one byte, so slow writers should be wrapped in `std::io::BufWriter`.
+#[derive(Debug)]
+pub struct WriteFile<W>(W);
+
+impl<W> WriteFile<W> {
+    pub fn new(writer: W) -> Self {
+        Self(writer)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.0
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.0
+    }
+
+    pub fn into_inner(self) -> W {
+        self.0
+    }
+}
+
+impl<W> File for WriteFile<W>
+where
+    W: io::Write + std::fmt::Debug + Send + 'static,
+{
+    fn read(&mut self) -> Result<Option<u8>, FileError> {
+        Err(FileError::ReadingNotAvailable)
+    }
+
+    fn write(&mut self, val: u8) -> Result<(), FileError> {
+        write_byte(&mut self.0, val)
+    }
+
+    fn write_bytes(&mut self, buf: &[u8]) -> Result<usize, FileError> {
+        write_from(&mut self.0, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), FileError> {
+        self.0.flush().map_err(file_error)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Readable and writable file over a type that implements both
+/// `std::io::Read` and `std::io::Write`, like `TcpStream` or `std::fs::File`.
+#[derive(Debug)]
+pub struct ReadWriteFile<S>(S);
+
+impl<S> ReadWriteFile<S> {
+    pub fn new(stream: S) -> Self {
+        Self(stream)
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.0
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.0
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<S> File for ReadWriteFile<S>
+where
+    S: io::Read + io::Write + std::fmt::Debug + Send + 'static,
+{
+    fn read(&mut self) -> Result<Option<u8>, FileError> {
+        read_byte(&mut self.0)
+    }
+
+    fn write(&mut self, val: u8) -> Result<(), FileError> {
+        write_byte(&mut self.0, val)
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, FileError> {
+        read_into(&mut self.0, buf)
+    }
+
+    fn write_bytes(&mut self, buf: &[u8]) -> Result<usize, FileError> {
+        write_from(&mut self.0, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), FileError> {
+        self.0.flush().map_err(file_error)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// File of a descriptor, returned by `Files::descriptor`.
+///
+/// Reads stop at the end of the file or when it would block. Writes to the
+/// file are not counted by the write limit of `Files`.
+#[derive(Debug)]
+pub struct Descriptor<'f> {
+    file: &'f mut dyn File,
+}
+
+impl<'f> Descriptor<'f> {
+    pub(super) fn new(file: &'f mut dyn File) -> Self {
+        Self { file }
+    }
+}
+
+impl io::Read for Descriptor<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read_bytes(buf).map_err(|e| io_error(e.into()))
+    }
+}
+
+impl io::Write for Descriptor<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write_bytes(buf).map_err(|e| io_error(e.into()))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush().map_err(|e| io_error(e.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::Files;
+    use std::{
+        collections::VecDeque,
+        io::{Cursor, Read, Write},
+    };
+
+    #[test]
+    fn io_files() {
+        let mut file = ReadFile::new(Cursor::new(vec![1, 2]));
+        assert_eq!(file.read(), Ok(Some(1)));
+        assert_eq!(file.read(), Ok(Some(2)));
+        assert_eq!(file.read(), Ok(None));
+        assert_eq!(file.write(0), Err(FileError::WritingNotAvailable));
+
+        let mut file = WriteFile::new(Vec::new());
+        file.write(3).unwrap();
+        file.flush().unwrap();
+        assert_eq!(file.into_inner(), [3]);
+
+        let mut file = ReadWriteFile::new(Cursor::new(vec![0; 2]));
+        file.write(4).unwrap();
+        assert_eq!(file.read(), Ok(Some(0)));
+        assert_eq!(file.into_inner().into_inner(), [4, 0]);
+
+        let mut file = WriteFile::new(Cursor::new([0; 1]));
+        file.write(5).unwrap();
+        assert_eq!(file.write(6), Err(FileError::Io(io::ErrorKind::WriteZero)));
+    }
+
+    #[test]
+    fn io_descriptor() {
+        let mut files = Files::new();
+        files.open(VecDeque::from(vec![1, 2, 3])).unwrap();
+        files.open(Vec::new()).unwrap();
+        files.set_current(1).unwrap();
+
+        let mut buf = [0; 4];
+        let mut input = files.descriptor(0).unwrap();
+        assert_eq!(input.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf, [1, 2, 3, 0]);
+
+        let mut output = files.descriptor(1).unwrap();
+        output.write_all(&[4, 5]).unwrap();
+        assert_eq!(
+            output.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+
+        files.write(6).unwrap();
+        let output = files.close(1).unwrap();
+        assert_eq!(
+            output.as_any().downcast_ref::<Vec<u8>>().unwrap(),
+            &[4, 5, 6]
+        );
+
+        assert_eq!(files.descriptor(1).unwrap_err(), FilesError::NotFound);
+    }
+}
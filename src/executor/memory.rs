@@ -1,5 +1,14 @@
-use super::primary::Primary;
+use super::{
+    allocator::Allocator,
+    device::Device,
+    executor::Location,
+    paging::{Permission, Storage},
+    primary::Primary,
+    sanitizer::{HeapAccess, Sanitizer},
+    snapshot::{Reader, SnapshotError, Writer},
+};
 use crate::common::UWord;
+use std::{borrow::Cow, collections::BTreeMap};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum MemoryError {
@@ -7,30 +16,179 @@ pub enum MemoryError {
     RageUnderflow(&'static str),
     SegmentationFault(UWord, UWord),
     WrongRange,
+    DoubleFree(UWord),
+    InvalidFree(UWord),
+    /// Read of `size` bytes at the pointer, some of which weren't written.
+    UninitializedRead(UWord, UWord),
+    /// Heap access outside of live allocations, reported by the sanitizer.
+    HeapOverflow(HeapAccess),
+    /// Heap access of a freed allocation, reported by the sanitizer.
+    UseAfterFree(HeapAccess),
+    /// Access of `size` bytes at the pointer to a page that doesn't permit it.
+    AccessViolation(UWord, UWord),
 }
 
 pub struct MemoryPage {
-    page: Vec<u8>,
+    storage: Storage,
     limit: usize,
     name: &'static str,
+    /// Shadow memory, tells for every byte whether it was written.
+    shadow: Option<Vec<bool>>,
+    /// Permissions of pages by their index. Other pages can be read and written.
+    permissions: BTreeMap<UWord, Permission>,
+    /// Accesses need more checks than the range, because the storage is paged, written
+    /// bytes are tracked or some pages are protected.
+    checked: bool,
 }
 
 impl MemoryPage {
-    fn new(limit: usize, name: &'static str) -> Self {
+    /// Size of pages of the paged storage and of page permissions.
+    pub const PAGE_SIZE: UWord = 4096;
+
+    pub(super) fn new(limit: usize, name: &'static str) -> Self {
         Self {
-            page: Vec::new(),
+            storage: Storage::Contiguous(Vec::new()),
             limit,
             name,
+            shadow: None,
+            permissions: BTreeMap::new(),
+            checked: false,
+        }
+    }
+
+    /// Creates a page which allocates memory by pages on the first write.
+    pub(super) fn paged(limit: usize, name: &'static str) -> Self {
+        Self {
+            storage: Storage::paged(),
+            checked: true,
+            ..Self::new(limit, name)
+        }
+    }
+
+    /// Creates an empty page with the same limit, name, storage, shadow mode and
+    /// page permissions.
+    pub(super) fn empty(&self) -> Self {
+        let mut page = Self {
+            storage: self.storage.empty(),
+            shadow: self.shadow.as_ref().map(|_| Vec::new()),
+            permissions: self.permissions.clone(),
+            ..Self::new(self.limit, self.name)
+        };
+
+        page.update_checked();
+        page
+    }
+
+    fn update_checked(&mut self) {
+        self.checked = self.is_paged() || self.shadow.is_some() || !self.permissions.is_empty();
+    }
+
+    /// Starts to track written bytes. Bytes already in the page count as written.
+    /// The shadow memory takes a byte for every byte of the page, even if it's paged.
+    pub(super) fn enable_shadow(&mut self) {
+        if self.shadow.is_none() {
+            self.shadow = Some(vec![true; self.storage.len()]);
+            self.checked = true;
+        }
+    }
+
+    pub fn is_shadowed(&self) -> bool {
+        self.shadow.is_some()
+    }
+
+    pub fn is_paged(&self) -> bool {
+        matches!(self.storage, Storage::Paged { .. })
+    }
+
+    /// Checks all `size` bytes at `ptr` were written. Without the shadow
+    /// memory every byte counts as written.
+    pub fn is_initialized(&self, ptr: UWord, size: UWord) -> bool {
+        match &self.shadow {
+            Some(shadow) => shadow
+                .get(ptr as usize..ptr.wrapping_add(size) as usize)
+                .is_some_and(|s| s.iter().all(|&init| init)),
+            None => true,
+        }
+    }
+
+    fn mark(&mut self, ptr: UWord, size: UWord, init: bool) {
+        if let Some(shadow) = &mut self.shadow {
+            let start = (ptr as usize).min(shadow.len());
+            let end = (ptr.saturating_add(size) as usize).min(shadow.len());
+            shadow[start..end].iter_mut().for_each(|s| *s = init);
+        }
+    }
+
+    /// Sets the `permission` of pages that `size` bytes at `ptr` touch. Pages can
+    /// be protected beyond the length of the page, but not beyond its limit.
+    pub fn protect(
+        &mut self,
+        ptr: UWord,
+        size: UWord,
+        permission: Permission,
+    ) -> Result<(), MemoryError> {
+        let end = ptr
+            .checked_add(size)
+            .filter(|&end| end as usize <= self.limit)
+            .ok_or(MemoryError::SegmentationFault(ptr, size))?;
+
+        if size == 0 {
+            return Ok(());
+        }
+
+        for page in ptr / Self::PAGE_SIZE..=(end - 1) / Self::PAGE_SIZE {
+            match permission {
+                Permission::ReadWrite => self.permissions.remove(&page),
+                permission => self.permissions.insert(page, permission),
+            };
+        }
+
+        self.update_checked();
+        Ok(())
+    }
+
+    pub fn permission(&self, ptr: UWord) -> Permission {
+        self.permissions
+            .get(&(ptr / Self::PAGE_SIZE))
+            .copied()
+            .unwrap_or(Permission::ReadWrite)
+    }
+
+    /// Checks that `size` bytes at `ptr` are in the page and allow the access.
+    pub fn check(&self, ptr: UWord, size: UWord, write: bool) -> Result<(), MemoryError> {
+        let end = ptr
+            .checked_add(size)
+            .filter(|&end| end <= self.len())
+            .ok_or(MemoryError::SegmentationFault(ptr, size))?;
+
+        if self.permissions.is_empty() || size == 0 {
+            return Ok(());
+        }
+
+        let denied = self
+            .permissions
+            .range(ptr / Self::PAGE_SIZE..=(end - 1) / Self::PAGE_SIZE)
+            .any(|(_, permission)| !permission.allows(write));
+
+        if denied {
+            Err(MemoryError::AccessViolation(ptr, size))
+        } else {
+            Ok(())
         }
     }
 
     pub fn expand(&mut self, size: UWord) -> Result<(), MemoryError> {
-        let len = self.page.len().saturating_add(size as usize);
+        let len = self.storage.len().saturating_add(size as usize);
 
         if len > self.limit {
             Err(MemoryError::PageOverflow(self.name))
         } else {
-            self.page.resize(len, 0);
+            self.storage.resize(len);
+
+            if let Some(shadow) = &mut self.shadow {
+                shadow.resize(len, false);
+            }
+
             Ok(())
         }
     }
@@ -38,48 +196,196 @@ impl MemoryPage {
     pub fn narrow(&mut self, size: UWord) -> Result<(), MemoryError> {
         let size = size as usize;
 
-        if self.page.len() < size {
+        if self.storage.len() < size {
             Err(MemoryError::PageOverflow(self.name))
         } else {
-            let len = self.page.len() - size;
-            self.page.truncate(len);
+            let len = self.storage.len() - size;
+            self.storage.resize(len);
+
+            if let Some(shadow) = &mut self.shadow {
+                shadow.truncate(len);
+            }
+
             Ok(())
         }
     }
 
     pub fn len(&self) -> UWord {
-        self.page.len() as UWord
+        self.storage.len() as UWord
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.storage.len() == 0
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns all bytes if accesses need no checks besides the range.
+    #[inline]
+    fn plain(&self) -> Option<&[u8]> {
+        match &self.storage {
+            Storage::Contiguous(bytes) if !self.checked => Some(bytes),
+            _ => None,
+        }
     }
 
+    #[inline]
+    fn plain_mut(&mut self) -> Option<&mut [u8]> {
+        match &mut self.storage {
+            Storage::Contiguous(bytes) if !self.checked => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns bytes to read. Bytes across pages of the paged storage can't be
+    /// borrowed, so getting them fails with `WrongRange`, `read` copies them.
     pub fn get(&self, ptr: UWord, size: UWord) -> Result<&[u8], MemoryError> {
-        self.page
-            .get(ptr as usize..ptr.wrapping_add(size) as usize)
-            .ok_or(MemoryError::SegmentationFault(ptr, size))
+        match self.read(ptr, size)? {
+            Cow::Borrowed(bytes) => Ok(bytes),
+            Cow::Owned(_) => Err(MemoryError::WrongRange),
+        }
     }
 
+    /// Returns bytes to write, so they are marked as written. Bytes across pages of
+    /// the paged storage can't be borrowed, so getting them fails with `WrongRange`.
     pub fn get_mut(&mut self, ptr: UWord, size: UWord) -> Result<&mut [u8], MemoryError> {
-        self.page
-            .get_mut(ptr as usize..ptr.wrapping_add(size) as usize)
-            .ok_or(MemoryError::SegmentationFault(ptr, size))
+        self.check(ptr, size, true)?;
+
+        if self.storage.is_split(ptr as usize, size as usize) {
+            return Err(MemoryError::WrongRange);
+        }
+
+        self.mark(ptr, size, true);
+        Ok(self.storage.get_mut(ptr as usize, size as usize))
+    }
+
+    /// Returns bytes to read. The bytes are borrowed unless they cross pages of
+    /// the paged storage.
+    pub fn read(&self, ptr: UWord, size: UWord) -> Result<Cow<'_, [u8]>, MemoryError> {
+        self.check(ptr, size, false)?;
+        Ok(self.storage.read(ptr as usize, size as usize))
+    }
+
+    /// Writes bytes, so they are marked as written.
+    pub fn set(&mut self, ptr: UWord, bytes: &[u8]) -> Result<(), MemoryError> {
+        let size = bytes.len() as UWord;
+        self.check(ptr, size, true)?;
+        self.mark(ptr, size, true);
+        self.storage.write(ptr as usize, bytes);
+
+        Ok(())
+    }
+
+    pub fn set_zeros(&mut self, ptr: UWord, size: UWord) -> Result<(), MemoryError> {
+        self.check(ptr, size, true)?;
+        self.mark(ptr, size, true);
+        self.storage.zero(ptr as usize, size as usize);
+
+        Ok(())
+    }
+
+    /// Returns a copy of all bytes, regardless of permissions.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.storage.read(0, self.storage.len()).into_owned()
+    }
+
+    /// Saves the length, non-zero pages and permissions.
+    pub(super) fn save(&self, w: &mut Writer) {
+        w.word(self.len());
+
+        let chunks: Vec<_> = self
+            .storage
+            .chunks()
+            .into_iter()
+            .filter(|(_, chunk)| chunk.iter().any(|&b| b != 0))
+            .collect();
+
+        w.word(chunks.len() as UWord);
+
+        for (ptr, chunk) in chunks {
+            w.word(ptr as UWord);
+            w.bytes(chunk);
+        }
+
+        w.word(self.permissions.len() as UWord);
+
+        for (&page, &permission) in self.permissions.iter() {
+            w.word(page);
+            w.word(permission as UWord);
+        }
+    }
+
+    /// Loads the page saved by `save` into an empty page. Loaded bytes count as written.
+    pub(super) fn load(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        let len = r.word()?;
+
+        if len as usize > self.limit {
+            return Err(MemoryError::PageOverflow(self.name).into());
+        }
+
+        self.storage.resize(len as usize);
+
+        for _ in 0..r.word()? {
+            let ptr = r.word()?;
+            let chunk = r.bytes()?;
+
+            if chunk.len() > Self::PAGE_SIZE as usize
+                || ptr
+                    .checked_add(chunk.len() as UWord)
+                    .filter(|&end| end <= len)
+                    .is_none()
+            {
+                return Err(SnapshotError::InvalidData);
+            }
+
+            self.storage.write(ptr as usize, chunk);
+        }
+
+        // Saved permissions replace the ones of the empty page
+        self.permissions.clear();
+
+        for _ in 0..r.word()? {
+            let page = r.word()?;
+            let permission = match r.word()? {
+                0 => Permission::None,
+                1 => Permission::Read,
+                2 => Permission::ReadWrite,
+                _ => return Err(SnapshotError::InvalidData),
+            };
+
+            self.permissions.insert(page, permission);
+        }
+
+        if let Some(shadow) = &mut self.shadow {
+            *shadow = vec![true; len as usize];
+        }
+
+        self.update_checked();
+        Ok(())
     }
 
     pub fn memmove(&mut self, dest: UWord, src: UWord, size: UWord) -> Result<(), MemoryError> {
-        let src_end = src.wrapping_add(size);
-        let dest_end = dest.wrapping_add(size);
+        if src.checked_add(size).is_none() {
+            return Err(MemoryError::WrongRange);
+        }
 
-        if src > src_end {
-            Err(MemoryError::WrongRange)
-        } else if src_end > self.len() {
-            Err(MemoryError::SegmentationFault(src, size))
-        } else if dest_end > self.len() {
-            Err(MemoryError::SegmentationFault(dest, size))
-        } else {
-            self.page
-                .as_mut_slice()
-                .copy_within(src as usize..src_end as usize, dest as usize);
+        self.check(src, size, false)?;
+        self.check(dest, size, true)?;
+        self.storage
+            .copy_within(src as usize, dest as usize, size as usize);
 
-            Ok(())
+        if let Some(shadow) = &mut self.shadow {
+            let src = src as usize;
+            shadow.copy_within(src..src + size as usize, dest as usize);
         }
+
+        Ok(())
     }
 }
 
@@ -87,27 +393,26 @@ impl std::fmt::Debug for MemoryPage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use std::fmt::Write;
 
-        if self.page.is_empty() {
-            return Ok(());
-        }
-
-        let mut counter = 0;
-        let mut line = 0;
+        // Paged storage shows only allocated pages
+        for (start, chunk) in self.storage.chunks() {
+            let mut counter = 0;
+            let mut line = start;
 
-        f.write_char('\n')?;
+            f.write_char('\n')?;
 
-        for &byte in self.page.iter() {
-            if counter == 0 {
-                write!(f, "{:02X?}:  ", line)?;
-            }
+            for &byte in chunk.iter() {
+                if counter == 0 {
+                    write!(f, "{:02X?}:  ", line)?;
+                }
 
-            write!(f, "{:02X?} ", byte)?;
-            counter += 1;
-            line += 1;
+                write!(f, "{:02X?} ", byte)?;
+                counter += 1;
+                line += 1;
 
-            if counter > 8 {
-                f.write_char('\n')?;
-                counter = 0;
+                if counter > 8 {
+                    f.write_char('\n')?;
+                    counter = 0;
+                }
             }
         }
 
@@ -119,6 +424,14 @@ impl std::fmt::Debug for MemoryPage {
 pub struct Memory {
     pub stack: MemoryPage,
     pub heap: MemoryPage,
+    allocator: Allocator,
+    watchpoints: Vec<(UWord, UWord)>,
+    watch_hit: Option<UWord>,
+    sanitizer: Option<Sanitizer>,
+    /// Mapped devices by the start of their range, with the size of the range.
+    devices: BTreeMap<UWord, (UWord, Box<dyn Device>)>,
+    /// Accesses need checks of the sanitizer, devices or watchpoints.
+    checked: bool,
 }
 
 impl Memory {
@@ -136,7 +449,275 @@ impl Memory {
         Self {
             stack: MemoryPage::new(stack_limit, "stack"),
             heap: MemoryPage::new(heap_limit, "heap"),
+            allocator: Allocator::new(),
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            sanitizer: None,
+            devices: BTreeMap::new(),
+            checked: false,
+        }
+    }
+
+    /// Creates a memory which pages allocate memory by pages on the first write,
+    /// so large limits don't take memory until it's used.
+    pub fn paged(stack_limit: usize, heap_limit: usize) -> Self {
+        Self {
+            stack: MemoryPage::paged(stack_limit, "stack"),
+            heap: MemoryPage::paged(heap_limit, "heap"),
+            ..Self::from_limits(stack_limit, heap_limit)
+        }
+    }
+
+    /// Sets the `permission` of stack or heap pages that `size` bytes at `ptr` touch.
+    /// Protected pages below the heap top work as guard pages.
+    pub fn protect(
+        &mut self,
+        ptr: UWord,
+        size: UWord,
+        permission: Permission,
+    ) -> Result<(), MemoryError> {
+        let (page, offset) = self.page_mut(ptr);
+        page.protect(offset, size, permission)
+    }
+
+    fn page(&self, ptr: UWord) -> (&MemoryPage, UWord) {
+        if ptr < Memory::HEAP_BASE {
+            (&self.stack, ptr)
+        } else {
+            (&self.heap, ptr - Memory::HEAP_BASE)
+        }
+    }
+
+    fn page_mut(&mut self, ptr: UWord) -> (&mut MemoryPage, UWord) {
+        if ptr < Memory::HEAP_BASE {
+            (&mut self.stack, ptr)
+        } else {
+            (&mut self.heap, ptr - Memory::HEAP_BASE)
+        }
+    }
+
+    fn update_checked(&mut self) {
+        self.checked =
+            self.sanitizer.is_some() || !self.devices.is_empty() || !self.watchpoints.is_empty();
+    }
+
+    /// Returns bytes of a page if the access needs no checks besides the range.
+    #[inline]
+    fn plain(&self, ptr: UWord, size: UWord) -> Option<Result<&[u8], MemoryError>> {
+        if self.checked {
+            return None;
+        }
+
+        let (page, offset) = self.page(ptr);
+        let bytes = page.plain()?;
+
+        Some(
+            bytes
+                .get(offset as usize..offset.wrapping_add(size) as usize)
+                .ok_or(MemoryError::SegmentationFault(offset, size)),
+        )
+    }
+
+    #[inline]
+    fn plain_mut(&mut self, ptr: UWord, size: UWord) -> Option<Result<&mut [u8], MemoryError>> {
+        if self.checked {
+            return None;
         }
+
+        let (page, offset) = self.page_mut(ptr);
+        let bytes = page.plain_mut()?;
+
+        Some(
+            bytes
+                .get_mut(offset as usize..offset.wrapping_add(size) as usize)
+                .ok_or(MemoryError::SegmentationFault(offset, size)),
+        )
+    }
+
+    pub fn add_watchpoint(&mut self, ptr: UWord, size: UWord) {
+        self.watchpoints.push((ptr, size));
+        self.checked = true;
+    }
+
+    pub fn remove_watchpoint(&mut self, ptr: UWord, size: UWord) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|&w| w != (ptr, size));
+        self.update_checked();
+        self.watchpoints.len() != len
+    }
+
+    /// Returns the pointer of the first write that touched a watchpoint since the last call.
+    pub fn take_watch_hit(&mut self) -> Option<UWord> {
+        self.watch_hit.take()
+    }
+
+    fn watch(&mut self, ptr: UWord, size: UWord) {
+        if self.watch_hit.is_some() {
+            return;
+        }
+
+        let end = ptr.saturating_add(size);
+        let touched = self
+            .watchpoints
+            .iter()
+            .any(|&(w, w_size)| ptr < w.saturating_add(w_size) && w < end);
+
+        if touched {
+            self.watch_hit = Some(ptr);
+        }
+    }
+
+    /// Maps the `device` to the range at `ptr`, so reads and writes of the range
+    /// call the device. The range must not be empty or overlap the space of stack
+    /// and heap pages up to their limits or ranges of other devices.
+    pub fn map_device<D>(&mut self, ptr: UWord, device: D) -> Result<(), MemoryError>
+    where
+        D: Device + 'static,
+    {
+        let size = device.size();
+        let end = ptr
+            .checked_add(size)
+            .filter(|_| size != 0)
+            .ok_or(MemoryError::WrongRange)?;
+        let overlaps = |start: UWord, len: UWord| ptr < start.saturating_add(len) && start < end;
+
+        if overlaps(0, self.stack.limit as UWord)
+            || overlaps(Memory::HEAP_BASE, self.heap.limit as UWord)
+            || self
+                .devices
+                .iter()
+                .any(|(&start, &(len, _))| overlaps(start, len))
+        {
+            return Err(MemoryError::WrongRange);
+        }
+
+        self.devices.insert(ptr, (size, Box::new(device)));
+        self.checked = true;
+        Ok(())
+    }
+
+    /// Unmaps the device which range starts at `ptr` and returns it.
+    pub fn unmap_device(&mut self, ptr: UWord) -> Option<Box<dyn Device>> {
+        let device = self.devices.remove(&ptr).map(|(_, device)| device);
+        self.update_checked();
+        device
+    }
+
+    pub fn device(&self, ptr: UWord) -> Option<&dyn Device> {
+        self.devices.get(&ptr).map(|(_, device)| device.as_ref())
+    }
+
+    /// Moves mapped devices from the `other` memory.
+    pub(super) fn take_devices(&mut self, other: &mut Memory) {
+        self.devices = std::mem::take(&mut other.devices);
+        self.update_checked();
+        other.update_checked();
+    }
+
+    /// Returns the start of the device range that `size` bytes at `ptr` fall into.
+    fn device_at(&self, ptr: UWord, size: UWord) -> Result<Option<UWord>, MemoryError> {
+        if self.devices.is_empty() {
+            return Ok(None);
+        }
+
+        match self.devices.range(..=ptr).next_back() {
+            Some((&start, &(len, _))) if ptr - start < len => {
+                if size > len - (ptr - start) {
+                    Err(MemoryError::SegmentationFault(ptr, size))
+                } else {
+                    Ok(Some(start))
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Checks every heap access against live allocations. Allocations get `red_zone`
+    /// bytes on both sides and freed allocations stay unavailable until more than
+    /// `quarantine` bytes are freed after them.
+    pub fn enable_sanitizer(&mut self, red_zone: UWord, quarantine: UWord) {
+        if self.sanitizer.is_none() {
+            self.sanitizer = Some(Sanitizer::new(red_zone, quarantine, &self.allocator));
+            self.checked = true;
+        }
+    }
+
+    pub fn alloc(&mut self, size: UWord) -> Result<UWord, MemoryError> {
+        self.alloc_at(size, None)
+    }
+
+    /// Allocates memory by the operation at the `site`, which is reported by the sanitizer.
+    pub fn alloc_at(&mut self, size: UWord, site: Option<Location>) -> Result<UWord, MemoryError> {
+        let ptr = match &mut self.sanitizer {
+            Some(sanitizer) => sanitizer.alloc(&mut self.allocator, &mut self.heap, size, site)?,
+            None => self.allocator.alloc(&mut self.heap, size)? + Memory::HEAP_BASE,
+        };
+
+        self.heap.mark(ptr - Memory::HEAP_BASE, size, false);
+        Ok(ptr)
+    }
+
+    pub fn free(&mut self, ptr: UWord) -> Result<(), MemoryError> {
+        self.free_at(ptr, None)
+    }
+
+    /// Frees memory by the operation at the `site`, which is reported by the sanitizer.
+    pub fn free_at(&mut self, ptr: UWord, site: Option<Location>) -> Result<(), MemoryError> {
+        if ptr < Memory::HEAP_BASE {
+            return Err(MemoryError::InvalidFree(ptr));
+        }
+
+        if let Some(sanitizer) = &mut self.sanitizer {
+            return sanitizer.free(&mut self.allocator, &mut self.heap, ptr, site);
+        }
+
+        self.allocator
+            .free(&mut self.heap, ptr - Memory::HEAP_BASE)
+            .map_err(|e| match e {
+                MemoryError::DoubleFree(_) => MemoryError::DoubleFree(ptr),
+                MemoryError::InvalidFree(_) => MemoryError::InvalidFree(ptr),
+                e => e,
+            })
+    }
+
+    /// Saves the pages, the allocator and the sanitizer state. Watchpoints are not saved.
+    pub(super) fn save(&self, w: &mut Writer) {
+        self.stack.save(w);
+        self.heap.save(w);
+        self.allocator.save(w);
+        w.bool(self.sanitizer.is_some());
+
+        if let Some(sanitizer) = &self.sanitizer {
+            sanitizer.save(w);
+        }
+    }
+
+    /// Tracks which bytes of the stack and heap were written since their frame or
+    /// allocation was created. Reads of bytes that were not written fail with
+    /// `UninitializedRead`. Bytes already in memory count as written.
+    pub fn enable_shadow(&mut self) {
+        self.stack.enable_shadow();
+        self.heap.enable_shadow();
+    }
+
+    /// Loads a memory with the same limits, storage and shadow mode as `self`.
+    /// Loaded bytes count as written.
+    pub(super) fn load(&self, r: &mut Reader) -> Result<Self, SnapshotError> {
+        let mut memory = Self::from_limits(self.stack.limit, self.heap.limit);
+        memory.stack = self.stack.empty();
+        memory.heap = self.heap.empty();
+        memory.stack.load(r)?;
+        memory.heap.load(r)?;
+        memory.allocator = Allocator::load(r, memory.heap.len())?;
+
+        if r.bool()? {
+            memory.sanitizer = Some(Sanitizer::load(r, &memory.allocator)?);
+        }
+
+        memory.watchpoints = self.watchpoints.clone();
+        memory.update_checked();
+
+        Ok(memory)
     }
 
     pub fn set<T>(&mut self, ptr: UWord, value: T) -> Result<(), MemoryError>
@@ -145,17 +726,19 @@ impl Memory {
     {
         use std::borrow::Borrow;
 
-        let dest = self.slice_mut(ptr, T::SIZE as UWord)?;
-        dest.copy_from_slice(value.to_bytes().borrow());
-        Ok(())
+        self.set_bytes(ptr, value.to_bytes().borrow())
     }
 
     pub fn get<T>(&self, ptr: UWord) -> Result<T, MemoryError>
     where
         T: Primary,
     {
-        let src = self.slice(ptr, T::SIZE as UWord)?;
-        Ok(T::from_slice(src))
+        if let Some(src) = self.plain(ptr, T::SIZE as UWord) {
+            return src.map(T::from_slice);
+        }
+
+        let src = self.read(ptr, T::SIZE as UWord)?;
+        Ok(T::from_slice(&src))
     }
 
     pub fn update<T, F>(&mut self, ptr: UWord, f: F) -> Result<(), MemoryError>
@@ -167,9 +750,21 @@ impl Memory {
     }
 
     pub fn copy(&mut self, dest: UWord, src: UWord, size: UWord) -> Result<(), MemoryError> {
+        if self.device_at(dest, size)?.is_some() || self.device_at(src, size)?.is_some() {
+            let bytes = self.read(src, size)?.into_owned();
+            return self.set_bytes(dest, &bytes);
+        }
+
         let dest_on_stack = dest < Memory::HEAP_BASE;
         let src_on_stack = src < Memory::HEAP_BASE;
 
+        self.check_heap(dest, size)?;
+        self.check_heap(src, size)?;
+
+        if !self.watchpoints.is_empty() {
+            self.watch(dest, size);
+        }
+
         // If dest and src are on the left or on the right side together then
         // they are in the same memory page.
         return if dest_on_stack == src_on_stack {
@@ -183,47 +778,152 @@ impl Memory {
             }
         } else {
             // Otherwise it requires to copy from one page to another.
-            let (dest_slice, src_slice) = if dest_on_stack {
-                let src = src - Memory::HEAP_BASE;
-                (self.stack.get_mut(dest, size)?, self.heap.get(src, size)?)
+            let (dest_page, dest, src_page, src) = if dest_on_stack {
+                (&mut self.stack, dest, &self.heap, src - Memory::HEAP_BASE)
             } else {
-                let dest = dest - Memory::HEAP_BASE;
-                (self.heap.get_mut(dest, size)?, self.stack.get(src, size)?)
+                (&mut self.heap, dest - Memory::HEAP_BASE, &self.stack, src)
             };
 
-            dest_slice.copy_from_slice(src_slice);
+            let src_slice = src_page.read(src, size)?;
+            dest_page.set(dest, &src_slice)?;
+
+            // Unwritten bytes stay unwritten in the copy
+            if let (Some(dest_shadow), Some(src_shadow)) = (&mut dest_page.shadow, &src_page.shadow)
+            {
+                dest_shadow[dest as usize..dest.wrapping_add(size) as usize]
+                    .copy_from_slice(&src_shadow[src as usize..src.wrapping_add(size) as usize]);
+            }
+
             Ok(())
         };
     }
 
+    pub fn set_bytes(&mut self, dest: UWord, bytes: &[u8]) -> Result<(), MemoryError> {
+        let size = bytes.len() as UWord;
+
+        if let Some(slice) = self.plain_mut(dest, size) {
+            slice?.copy_from_slice(bytes);
+            return Ok(());
+        }
+
+        if let Some(start) = self.device_at(dest, size)? {
+            if !self.watchpoints.is_empty() {
+                self.watch(dest, size);
+            }
+
+            if let Some((_, device)) = self.devices.get_mut(&start) {
+                device.write(dest - start, bytes);
+            }
+
+            return Ok(());
+        }
+
+        self.check_heap(dest, size)?;
+
+        if !self.watchpoints.is_empty() {
+            self.watch(dest, size);
+        }
+
+        let (page, offset) = self.page_mut(dest);
+        page.set(offset, bytes)
+    }
+
     pub fn set_zeros(&mut self, dest: UWord, size: UWord) -> Result<(), MemoryError> {
-        let slice = self.slice_mut(dest, size)?;
-        slice.iter_mut().for_each(|b| *b = 0);
+        if let Some(slice) = self.plain_mut(dest, size) {
+            slice?.fill(0);
+            return Ok(());
+        }
 
-        Ok(())
+        if self.device_at(dest, size)?.is_some() {
+            return self.set_bytes(dest, &vec![0; size as usize]);
+        }
+
+        self.check_heap(dest, size)?;
+
+        if !self.watchpoints.is_empty() {
+            self.watch(dest, size);
+        }
+
+        let (page, offset) = self.page_mut(dest);
+        page.set_zeros(offset, size)
     }
 
     pub fn compare(&self, a: UWord, b: UWord, size: UWord) -> Result<bool, MemoryError> {
-        let a_slice = self.slice(a, size)?;
-        let b_slice = self.slice(b, size)?;
+        let a_slice = self.read(a, size)?;
+        let b_slice = self.read(b, size)?;
         Ok(a_slice == b_slice)
     }
 
-    fn slice(&self, ptr: UWord, size: UWord) -> Result<&[u8], MemoryError> {
-        if ptr < Memory::HEAP_BASE {
-            self.stack.get(ptr, size)
+    /// Returns bytes to read. Device ranges and bytes across pages of the paged storage
+    /// can't be borrowed, so getting them fails with `WrongRange`, `read` copies them.
+    /// With the shadow memory all bytes must be written.
+    pub fn slice(&self, ptr: UWord, size: UWord) -> Result<&[u8], MemoryError> {
+        if let Some(bytes) = self.plain(ptr, size) {
+            return bytes;
+        }
+
+        if self.device_at(ptr, size)?.is_some() {
+            return Err(MemoryError::WrongRange);
+        }
+
+        self.check_heap(ptr, size)?;
+
+        let (page, offset) = self.page(ptr);
+        let slice = page.get(offset, size)?;
+
+        if page.is_initialized(offset, size) {
+            Ok(slice)
         } else {
-            let ptr = ptr - Memory::HEAP_BASE;
-            self.heap.get(ptr, size)
+            Err(MemoryError::UninitializedRead(ptr, size))
         }
     }
 
-    fn slice_mut(&mut self, ptr: UWord, size: UWord) -> Result<&mut [u8], MemoryError> {
-        if ptr < Memory::HEAP_BASE {
-            self.stack.get_mut(ptr, size)
+    /// Returns bytes to read from a page or a device. Bytes are copied if they come
+    /// from a device or cross pages of the paged storage. With the shadow memory all
+    /// bytes must be written.
+    pub fn read(&self, ptr: UWord, size: UWord) -> Result<Cow<'_, [u8]>, MemoryError> {
+        if let Some(bytes) = self.plain(ptr, size) {
+            return bytes.map(Cow::Borrowed);
+        }
+
+        if let Some(start) = self.device_at(ptr, size)? {
+            let mut buf = vec![0; size as usize];
+
+            if let Some((_, device)) = self.devices.get(&start) {
+                device.read(ptr - start, &mut buf);
+            }
+
+            return Ok(Cow::Owned(buf));
+        }
+
+        self.check_heap(ptr, size)?;
+
+        let (page, offset) = self.page(ptr);
+        let slice = page.read(offset, size)?;
+
+        if page.is_initialized(offset, size) {
+            Ok(slice)
         } else {
-            let ptr = ptr - Memory::HEAP_BASE;
-            self.heap.get_mut(ptr, size)
+            Err(MemoryError::UninitializedRead(ptr, size))
+        }
+    }
+
+    /// Checks that `size` bytes at `ptr` can be written, without writing them.
+    pub(super) fn check_write(&self, ptr: UWord, size: UWord) -> Result<(), MemoryError> {
+        if self.device_at(ptr, size)?.is_some() {
+            return Ok(());
+        }
+
+        self.check_heap(ptr, size)?;
+
+        let (page, offset) = self.page(ptr);
+        page.check(offset, size, true)
+    }
+
+    fn check_heap(&self, ptr: UWord, size: UWord) -> Result<(), MemoryError> {
+        match &self.sanitizer {
+            Some(sanitizer) if ptr >= Memory::HEAP_BASE => sanitizer.check(ptr, size),
+            _ => Ok(()),
         }
     }
 }
@@ -237,7 +937,7 @@ mod tests {
         let mut mem = Memory::from_limits(2048, 2048);
         mem.stack.expand(4).unwrap();
         assert_eq!(mem.stack.len(), 4);
-        assert_eq!(mem.stack.page.as_slice(), [0, 0, 0, 0]);
+        assert_eq!(mem.stack.to_vec(), [0, 0, 0, 0]);
 
         let mut mem = Memory::from_limits(2048, 2048);
         assert_eq!(
@@ -251,7 +951,7 @@ mod tests {
         let mut mem = Memory::from_limits(2048, 2048);
         mem.stack.expand(9).unwrap();
         mem.set(1, 0xFF000F0A_usize).unwrap();
-        assert_eq!(mem.stack.page.as_slice(), [0, 10, 15, 0, 255, 0, 0, 0, 0]);
+        assert_eq!(mem.stack.to_vec(), [0, 10, 15, 0, 255, 0, 0, 0, 0]);
 
         let value: usize = mem.get(1).unwrap();
         assert_eq!(value, 0xFF000F0A_usize);
@@ -262,7 +962,7 @@ mod tests {
         let mut mem = Memory::from_limits(2048, 2048);
         mem.heap.expand(9).unwrap();
         mem.set(Memory::HEAP_BASE + 1, 0xFF000F0A_usize).unwrap();
-        assert_eq!(mem.heap.page.as_slice(), [0, 10, 15, 0, 255, 0, 0, 0, 0]);
+        assert_eq!(mem.heap.to_vec(), [0, 10, 15, 0, 255, 0, 0, 0, 0]);
 
         let value: usize = mem.get(Memory::HEAP_BASE + 1).unwrap();
         assert_eq!(value, 0xFF000F0A_usize);
@@ -294,7 +994,7 @@ mod tests {
         mem.heap.expand(8).unwrap();
         mem.copy(Memory::HEAP_BASE, 0, 8).unwrap();
 
-        assert_eq!(mem.stack.page.as_slice(), mem.heap.page.as_slice());
+        assert_eq!(mem.stack.to_vec(), mem.heap.to_vec());
 
         let mut mem = Memory::from_limits(2048, 2048);
         mem.heap.expand(8).unwrap();
@@ -303,7 +1003,7 @@ mod tests {
         mem.stack.expand(8).unwrap();
         mem.copy(0, Memory::HEAP_BASE, 8).unwrap();
 
-        assert_eq!(mem.stack.page.as_slice(), mem.heap.page.as_slice());
+        assert_eq!(mem.stack.to_vec(), mem.heap.to_vec());
     }
 
     #[test]
@@ -314,7 +1014,7 @@ mod tests {
 
         mem.copy(8, 0, 8).unwrap();
         assert_eq!(
-            mem.stack.page.as_slice(),
+            mem.stack.to_vec(),
             [4, 255, 0, 0, 0, 0, 0, 0, 4, 255, 0, 0, 0, 0, 0, 0,]
         );
 
@@ -325,7 +1025,7 @@ mod tests {
         mem.copy(Memory::HEAP_BASE + 8, Memory::HEAP_BASE, 8)
             .unwrap();
         assert_eq!(
-            mem.heap.page.as_slice(),
+            mem.heap.to_vec(),
             [4, 255, 0, 0, 0, 0, 0, 0, 4, 255, 0, 0, 0, 0, 0, 0,]
         );
     }
@@ -362,7 +1062,7 @@ mod tests {
         mem.set(8, 0xFFFF).unwrap();
         mem.set_zeros(0, 16).unwrap();
 
-        assert!(mem.stack.page.iter().all(|b| *b == 0));
+        assert!(mem.stack.to_vec().iter().all(|b| *b == 0));
 
         let mut mem = Memory::from_limits(2048, 2048);
         mem.heap.expand(16).unwrap();
@@ -370,6 +1070,195 @@ mod tests {
         mem.set(Memory::HEAP_BASE + 8, 0xFFFF).unwrap();
         mem.set_zeros(Memory::HEAP_BASE, 16).unwrap();
 
-        assert!(mem.heap.page.iter().all(|b| *b == 0));
+        assert!(mem.heap.to_vec().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn memory_alloc_free() {
+        let mut mem = Memory::from_limits(2048, 2048);
+        let a = mem.alloc(4).unwrap();
+        let b = mem.alloc(4).unwrap();
+        assert_eq!(a, Memory::HEAP_BASE);
+        assert!(b > a);
+
+        mem.set(b, 0xFF32_u32).unwrap();
+        assert_eq!(mem.get::<u32>(b), Ok(0xFF32));
+
+        mem.free(a).unwrap();
+        assert_eq!(mem.free(a), Err(MemoryError::DoubleFree(a)));
+        assert_eq!(mem.free(0), Err(MemoryError::InvalidFree(0)));
+
+        mem.free(b).unwrap();
+        assert_eq!(mem.heap.len(), 0);
+    }
+
+    #[test]
+    fn memory_watchpoints() {
+        let mut mem = Memory::from_limits(2048, 2048);
+        mem.stack.expand(16).unwrap();
+        mem.add_watchpoint(4, 4);
+
+        mem.set(0, 1_u32).unwrap();
+        mem.set(8, 1_u32).unwrap();
+        assert_eq!(mem.take_watch_hit(), None);
+
+        mem.set(2, 1_u32).unwrap();
+        assert_eq!(mem.take_watch_hit(), Some(2));
+        assert_eq!(mem.take_watch_hit(), None);
+
+        mem.copy(6, 12, 2).unwrap();
+        assert_eq!(mem.take_watch_hit(), Some(6));
+
+        assert!(mem.remove_watchpoint(4, 4));
+        mem.set_zeros(0, 16).unwrap();
+        assert_eq!(mem.take_watch_hit(), None);
+    }
+
+    #[test]
+    fn memory_shadow() {
+        let mut mem = Memory::from_limits(2048, 2048);
+        mem.stack.expand(4).unwrap();
+        mem.set(0, 1_u16).unwrap();
+        mem.enable_shadow();
+        mem.stack.expand(12).unwrap();
+
+        assert_eq!(mem.get::<u32>(0), Ok(1));
+        assert_eq!(mem.get::<u32>(4), Err(MemoryError::UninitializedRead(4, 4)));
+        assert_eq!(
+            mem.compare(0, 2, 4),
+            Err(MemoryError::UninitializedRead(2, 4))
+        );
+
+        mem.set(4, 2_u32).unwrap();
+        mem.copy(8, 2, 8).unwrap();
+        assert_eq!(mem.get::<u32>(10), Ok(2));
+        assert_eq!(
+            mem.get::<u32>(12),
+            Err(MemoryError::UninitializedRead(12, 4))
+        );
+
+        mem.stack.narrow(8).unwrap();
+        mem.stack.expand(8).unwrap();
+        assert_eq!(mem.get::<u8>(8), Err(MemoryError::UninitializedRead(8, 1)));
+
+        let a = mem.alloc(8).unwrap();
+        mem.copy(a, 0, 8).unwrap();
+        assert_eq!(mem.get::<u32>(a + 4), Ok(2));
+        assert_eq!(mem.compare(a, 0, 8), Ok(true));
+
+        let b = mem.alloc(8).unwrap();
+        mem.free(a).unwrap();
+        assert_eq!(mem.alloc(8), Ok(a));
+        assert_eq!(mem.get::<u32>(a), Err(MemoryError::UninitializedRead(a, 4)));
+        assert_eq!(mem.get::<u32>(b), Err(MemoryError::UninitializedRead(b, 4)));
+    }
+
+    #[test]
+    fn memory_devices() {
+        let mut mem = Memory::from_limits(2048, 2048);
+        let ptr = 4096;
+
+        assert_eq!(
+            mem.map_device(2040, vec![0; 16]),
+            Err(MemoryError::WrongRange)
+        );
+        assert_eq!(
+            mem.map_device(Memory::HEAP_BASE + 2040, vec![0; 16]),
+            Err(MemoryError::WrongRange)
+        );
+        mem.map_device(ptr, vec![0; 8]).unwrap();
+        assert_eq!(
+            mem.map_device(ptr + 4, vec![0; 8]),
+            Err(MemoryError::WrongRange)
+        );
+
+        // An empty device would replace the device at its start
+        assert_eq!(mem.map_device(ptr, vec![]), Err(MemoryError::WrongRange));
+        assert_eq!(mem.device(ptr).map(|device| device.size()), Some(8));
+
+        mem.set(ptr, 0xFF04_u16).unwrap();
+        mem.update(ptr, |v: u16| v + 1).unwrap();
+        assert_eq!(mem.get::<u16>(ptr), Ok(0xFF05));
+        assert_eq!(
+            mem.get::<u32>(ptr + 6),
+            Err(MemoryError::SegmentationFault(ptr + 6, 4))
+        );
+
+        mem.stack.expand(4).unwrap();
+        mem.copy(0, ptr, 2).unwrap();
+        mem.copy(ptr + 4, 0, 4).unwrap();
+        assert_eq!(mem.compare(ptr, ptr + 4, 2), Ok(true));
+        assert_eq!(mem.read(ptr, 2).as_deref(), Ok(&[5, 255][..]));
+        assert_eq!(mem.slice(ptr, 2), Err(MemoryError::WrongRange));
+        mem.set_zeros(ptr, 2).unwrap();
+
+        let device = mem.unmap_device(ptr).unwrap();
+        assert_eq!(
+            device.as_any().downcast_ref::<Vec<u8>>().unwrap(),
+            &[0, 0, 0, 0, 5, 255, 0, 0]
+        );
+        assert!(mem.device(ptr).is_none());
+        assert_eq!(mem.slice(0, 2), Ok(&[5, 255][..]));
+        assert_eq!(mem.get::<u32>(2), Err(MemoryError::SegmentationFault(2, 4)));
+    }
+
+    #[test]
+    fn memory_paged() {
+        const PAGE: UWord = MemoryPage::PAGE_SIZE;
+
+        let mut mem = Memory::paged(2048, 1 << 24);
+        let ptr = mem.alloc(1 << 20).unwrap();
+        assert!(mem.heap.is_paged());
+        assert_eq!(mem.heap.len(), 1 << 20);
+
+        mem.set(ptr + PAGE - 2, 0xFF04_u32).unwrap();
+        assert_eq!(mem.get::<u32>(ptr + PAGE - 2), Ok(0xFF04));
+        assert_eq!(mem.get::<u32>(ptr + PAGE * 200), Ok(0));
+
+        let offset = ptr - Memory::HEAP_BASE;
+        assert_eq!(
+            mem.heap.get(offset + PAGE - 2, 4),
+            Err(MemoryError::WrongRange)
+        );
+        assert_eq!(
+            mem.heap.read(offset + PAGE - 2, 4).as_deref(),
+            Ok(&[4, 0xFF, 0, 0][..])
+        );
+        assert_eq!(mem.slice(ptr + PAGE - 2, 4), Err(MemoryError::WrongRange));
+        assert_eq!(
+            mem.heap.get_mut(offset + PAGE * 5 - 1, 2),
+            Err(MemoryError::WrongRange)
+        );
+        mem.heap
+            .get_mut(offset + PAGE * 4, 2)
+            .unwrap()
+            .copy_from_slice(&[1, 2]);
+        assert_eq!(mem.get::<u16>(ptr + PAGE * 4), Ok(0x0201));
+
+        mem.protect(ptr, PAGE, Permission::Read).unwrap();
+        assert_eq!(mem.set(ptr, 1_u32), Err(MemoryError::AccessViolation(0, 4)));
+        assert_eq!(
+            mem.copy(ptr + PAGE - 2, ptr + PAGE * 2, 4),
+            Err(MemoryError::AccessViolation(PAGE - 2, 4))
+        );
+        mem.copy(ptr + PAGE * 2, ptr + PAGE - 2, 4).unwrap();
+        assert_eq!(mem.compare(ptr + PAGE * 2, ptr + PAGE - 2, 4), Ok(true));
+
+        mem.protect(ptr + PAGE * 2, 1, Permission::None).unwrap();
+        assert_eq!(mem.heap.permission(PAGE * 2), Permission::None);
+        assert_eq!(
+            mem.get::<u32>(ptr + PAGE * 2),
+            Err(MemoryError::AccessViolation(PAGE * 2, 4))
+        );
+        mem.protect(ptr, PAGE * 3, Permission::ReadWrite).unwrap();
+        assert_eq!(mem.get::<u32>(ptr + PAGE * 2), Ok(0xFF04));
+
+        mem.stack.expand(16).unwrap();
+        mem.protect(8, 8, Permission::None).unwrap();
+        assert_eq!(mem.set_zeros(0, 4), Err(MemoryError::AccessViolation(0, 4)));
+        assert_eq!(
+            mem.protect(2040, 16, Permission::None),
+            Err(MemoryError::SegmentationFault(2040, 16))
+        );
     }
 }
@@ -1,6 +1,23 @@
+mod allocator;
+mod device;
 mod executor;
 mod files;
+mod io;
 mod memory;
+mod paging;
+mod pipe;
 pub mod primary;
+mod sanitizer;
+mod scheduler;
+mod snapshot;
 
+pub use device::Device;
 pub use executor::*;
+pub use files::{File, FileError, FileResolver, FileSnapshot, Files, FilesError};
+pub use io::{Descriptor, ReadFile, ReadWriteFile, WriteFile};
+pub use memory::{Memory, MemoryError, MemoryPage};
+pub use paging::Permission;
+pub use pipe::{pipe, Pipe, PipeReader, PipeWriter};
+pub use sanitizer::{Allocation, HeapAccess};
+pub use scheduler::{Clock, RealClock, Scheduler, TaskState, VirtualClock};
+pub use snapshot::SnapshotError;
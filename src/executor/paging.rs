@@ -0,0 +1,243 @@
+use super::memory::MemoryPage;
+use std::{borrow::Cow, collections::BTreeMap};
+
+const PAGE_SIZE: usize = MemoryPage::PAGE_SIZE as usize;
+
+/// Bytes of a page that was never written.
+static ZEROS: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+
+/// Access allowed to a page of memory.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Permission {
+    None,
+    Read,
+    ReadWrite,
+}
+
+impl Permission {
+    pub(super) fn allows(self, write: bool) -> bool {
+        match self {
+            Permission::None => false,
+            Permission::Read => !write,
+            Permission::ReadWrite => true,
+        }
+    }
+}
+
+/// Bytes of a memory page.
+///
+/// Contiguous storage keeps all bytes in one vector. Paged storage keeps fixed-size
+/// frames which are allocated on the first write, so never written bytes don't take
+/// any memory. Callers check ranges before the access.
+#[derive(Debug)]
+pub(super) enum Storage {
+    Contiguous(Vec<u8>),
+    Paged {
+        frames: BTreeMap<usize, Box<[u8]>>,
+        len: usize,
+    },
+}
+
+/// Splits `size` bytes at `ptr` by pages. Returns the page, the offset in the page,
+/// the offset from `ptr` and the length of every part.
+fn parts(ptr: usize, size: usize) -> impl Iterator<Item = (usize, usize, usize, usize)> {
+    let end = ptr + size;
+    let mut pos = ptr;
+
+    std::iter::from_fn(move || {
+        if pos >= end {
+            return None;
+        }
+
+        let offset = pos % PAGE_SIZE;
+        let len = (PAGE_SIZE - offset).min(end - pos);
+        let part = (pos / PAGE_SIZE, offset, pos - ptr, len);
+
+        pos += len;
+        Some(part)
+    })
+}
+
+impl Storage {
+    pub fn paged() -> Self {
+        Storage::Paged {
+            frames: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// Creates an empty storage of the same kind.
+    pub fn empty(&self) -> Self {
+        match self {
+            Storage::Contiguous(_) => Storage::Contiguous(Vec::new()),
+            Storage::Paged { .. } => Self::paged(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Storage::Contiguous(bytes) => bytes.len(),
+            Storage::Paged { len, .. } => *len,
+        }
+    }
+
+    /// Grows with zeros or shrinks to the `new_len`.
+    pub fn resize(&mut self, new_len: usize) {
+        match self {
+            Storage::Contiguous(bytes) => bytes.resize(new_len, 0),
+            Storage::Paged { frames, len } => {
+                if new_len < *len {
+                    let (page, offset) = (new_len / PAGE_SIZE, new_len % PAGE_SIZE);
+
+                    // Removed bytes must be zeros when the storage grows again
+                    frames.split_off(&(page + usize::from(offset != 0)));
+
+                    if let Some(frame) = frames.get_mut(&page) {
+                        frame[offset..].fill(0);
+                    }
+                }
+
+                *len = new_len;
+            }
+        }
+    }
+
+    pub fn read(&self, ptr: usize, size: usize) -> Cow<'_, [u8]> {
+        let frames = match self {
+            Storage::Contiguous(bytes) => return Cow::Borrowed(&bytes[ptr..ptr + size]),
+            Storage::Paged { frames, .. } => frames,
+        };
+
+        let offset = ptr % PAGE_SIZE;
+
+        if offset + size <= PAGE_SIZE {
+            let frame = frames
+                .get(&(ptr / PAGE_SIZE))
+                .map_or(&ZEROS[..], |f| &f[..]);
+            return Cow::Borrowed(&frame[offset..offset + size]);
+        }
+
+        let mut buf = vec![0; size];
+
+        for (page, offset, pos, len) in parts(ptr, size) {
+            if let Some(frame) = frames.get(&page) {
+                buf[pos..pos + len].copy_from_slice(&frame[offset..offset + len]);
+            }
+        }
+
+        Cow::Owned(buf)
+    }
+
+    /// Checks whether `size` bytes at `ptr` cross frames of the paged storage.
+    pub fn is_split(&self, ptr: usize, size: usize) -> bool {
+        match self {
+            Storage::Contiguous(_) => false,
+            Storage::Paged { .. } => ptr % PAGE_SIZE + size > PAGE_SIZE,
+        }
+    }
+
+    /// Returns bytes to write, which must not be split. Paged storage allocates
+    /// the frame of the bytes.
+    pub fn get_mut(&mut self, ptr: usize, size: usize) -> &mut [u8] {
+        let frames = match self {
+            Storage::Contiguous(bytes) => return &mut bytes[ptr..ptr + size],
+            Storage::Paged { frames, .. } => frames,
+        };
+
+        if size == 0 {
+            return &mut [];
+        }
+
+        let offset = ptr % PAGE_SIZE;
+        let frame = frames
+            .entry(ptr / PAGE_SIZE)
+            .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+
+        &mut frame[offset..offset + size]
+    }
+
+    pub fn write(&mut self, ptr: usize, bytes: &[u8]) {
+        let frames = match self {
+            Storage::Contiguous(page) => {
+                page[ptr..ptr + bytes.len()].copy_from_slice(bytes);
+                return;
+            }
+            Storage::Paged { frames, .. } => frames,
+        };
+
+        for (page, offset, pos, len) in parts(ptr, bytes.len()) {
+            let frame = frames
+                .entry(page)
+                .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+
+            frame[offset..offset + len].copy_from_slice(&bytes[pos..pos + len]);
+        }
+    }
+
+    /// Writes zeros. Paged storage doesn't allocate frames for them.
+    pub fn zero(&mut self, ptr: usize, size: usize) {
+        match self {
+            Storage::Contiguous(bytes) => bytes[ptr..ptr + size].fill(0),
+            Storage::Paged { frames, .. } => {
+                for (page, offset, _, len) in parts(ptr, size) {
+                    if let Some(frame) = frames.get_mut(&page) {
+                        frame[offset..offset + len].fill(0);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn copy_within(&mut self, src: usize, dest: usize, size: usize) {
+        match self {
+            Storage::Contiguous(bytes) => bytes.copy_within(src..src + size, dest),
+            Storage::Paged { .. } => {
+                let bytes = self.read(src, size).into_owned();
+                self.write(dest, &bytes);
+            }
+        }
+    }
+
+    /// Returns offsets and bytes of the parts which may be non-zero, in order.
+    pub fn chunks(&self) -> Vec<(usize, &[u8])> {
+        match self {
+            Storage::Contiguous(bytes) => bytes
+                .chunks(PAGE_SIZE)
+                .enumerate()
+                .map(|(page, chunk)| (page * PAGE_SIZE, chunk))
+                .collect(),
+            Storage::Paged { frames, len } => frames
+                .iter()
+                .map(|(&page, frame)| {
+                    let start = page * PAGE_SIZE;
+                    (start, &frame[..PAGE_SIZE.min(len - start)])
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paging_storage() {
+        let mut storage = Storage::paged();
+        storage.resize(PAGE_SIZE * 1024);
+        assert_eq!(storage.read(PAGE_SIZE * 512, 4), Cow::Borrowed(&[0; 4][..]));
+
+        storage.write(PAGE_SIZE - 2, &[1, 2, 3, 4]);
+        storage.zero(PAGE_SIZE * 8, PAGE_SIZE);
+        assert_eq!(storage.chunks().len(), 2);
+        assert_eq!(*storage.read(PAGE_SIZE - 2, 4), [1, 2, 3, 4]);
+
+        storage.copy_within(PAGE_SIZE - 2, PAGE_SIZE * 3 - 1, 4);
+        assert_eq!(*storage.read(PAGE_SIZE * 3 - 1, 4), [1, 2, 3, 4]);
+
+        storage.resize(PAGE_SIZE - 1);
+        storage.resize(PAGE_SIZE * 4);
+        assert_eq!(*storage.read(PAGE_SIZE - 2, 4), [1, 0, 0, 0]);
+        assert_eq!(storage.chunks().len(), 1);
+    }
+}
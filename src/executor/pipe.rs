@@ -0,0 +1,352 @@
+use super::files::{File, FileError};
+use std::{
+    any::Any,
+    collections::VecDeque,
+    io,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+};
+
+#[derive(Debug)]
+struct State {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    readers: usize,
+    writers: usize,
+}
+
+impl State {
+    fn is_readable(&self) -> bool {
+        !self.buf.is_empty() || self.writers == 0
+    }
+
+    fn is_writable(&self) -> bool {
+        self.buf.len() < self.capacity || self.readers == 0
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+/// Bounded byte buffer shared by reader and writer files.
+///
+/// Writes to a full pipe and reads from an empty pipe would block. When all
+/// writers are closed, reads from the empty pipe return the end of the file.
+/// When all readers are closed, writes fail with `BrokenPipe`.
+#[derive(Clone, Debug)]
+pub struct Pipe {
+    shared: Arc<Shared>,
+}
+
+impl Pipe {
+    /// Creates a pipe with the `capacity`, which is at least one byte, so writes
+    /// can make progress.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    buf: VecDeque::with_capacity(capacity),
+                    capacity,
+                    readers: 0,
+                    writers: 0,
+                }),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Creates a reader end of the pipe.
+    pub fn reader(&self) -> PipeReader {
+        self.lock().readers += 1;
+        PipeReader(self.clone())
+    }
+
+    /// Creates a writer end of the pipe.
+    pub fn writer(&self) -> PipeWriter {
+        self.lock().writers += 1;
+        PipeWriter(self.clone())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().buf.is_empty()
+    }
+
+    /// Checks that the pipe has data or all writers are closed, so reads don't block.
+    pub fn is_readable(&self) -> bool {
+        self.lock().is_readable()
+    }
+
+    /// Checks that the pipe has free space or all readers are closed, so writes
+    /// don't block.
+    pub fn is_writable(&self) -> bool {
+        self.lock().is_writable()
+    }
+
+    /// Blocks until the pipe has data or all writers are closed.
+    pub fn wait_readable(&self) {
+        let state = self.lock();
+        let _state = self
+            .shared
+            .changed
+            .wait_while(state, |s| !s.is_readable())
+            .unwrap_or_else(|e| e.into_inner());
+    }
+
+    /// Blocks until the pipe has free space or all readers are closed.
+    pub fn wait_writable(&self) {
+        let state = self.lock();
+        let _state = self
+            .shared
+            .changed
+            .wait_while(state, |s| !s.is_writable())
+            .unwrap_or_else(|e| e.into_inner());
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn read_bytes(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+        let mut state = self.lock();
+
+        if state.buf.is_empty() {
+            return match state.writers {
+                0 => Ok(0),
+                _ => Err(FileError::WouldBlock),
+            };
+        }
+
+        let len = buf.len().min(state.buf.len());
+
+        for (byte, val) in buf.iter_mut().zip(state.buf.drain(..len)) {
+            *byte = val;
+        }
+
+        self.shared.changed.notify_all();
+        Ok(len)
+    }
+
+    fn write_bytes(&self, buf: &[u8]) -> Result<usize, FileError> {
+        let mut state = self.lock();
+
+        if state.readers == 0 {
+            return Err(FileError::Io(io::ErrorKind::BrokenPipe));
+        }
+
+        let len = buf
+            .len()
+            .min(state.capacity.saturating_sub(state.buf.len()));
+
+        if len == 0 && !buf.is_empty() {
+            return Err(FileError::WouldBlock);
+        }
+
+        state.buf.extend(&buf[..len]);
+        self.shared.changed.notify_all();
+        Ok(len)
+    }
+
+    /// Reads all bytes of `buf` at once. Fewer bytes are read only when all
+    /// writers are closed.
+    fn read_value(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+        let mut state = self.lock();
+
+        if state.buf.len() < buf.len() && state.writers != 0 {
+            return Err(FileError::WouldBlock);
+        }
+
+        let len = buf.len().min(state.buf.len());
+
+        for (byte, val) in buf.iter_mut().zip(state.buf.drain(..len)) {
+            *byte = val;
+        }
+
+        self.shared.changed.notify_all();
+        Ok(len)
+    }
+
+    /// Writes all bytes of `buf` at once. A value larger than the capacity is
+    /// written when the pipe is empty, so it can make progress.
+    fn write_value(&self, buf: &[u8]) -> Result<usize, FileError> {
+        let mut state = self.lock();
+
+        if state.readers == 0 {
+            return Err(FileError::Io(io::ErrorKind::BrokenPipe));
+        }
+
+        let free = state.capacity.saturating_sub(state.buf.len());
+
+        if buf.len() > free && !state.buf.is_empty() {
+            return Err(FileError::WouldBlock);
+        }
+
+        state.buf.extend(buf);
+        self.shared.changed.notify_all();
+        Ok(buf.len())
+    }
+}
+
+/// Creates a pipe with the `capacity` and returns its writer and reader ends.
+pub fn pipe(capacity: usize) -> (PipeWriter, PipeReader) {
+    let pipe = Pipe::new(capacity);
+    (pipe.writer(), pipe.reader())
+}
+
+/// Reader end of a pipe. The reader is closed when it's dropped.
+#[derive(Debug)]
+pub struct PipeReader(Pipe);
+
+impl PipeReader {
+    pub fn pipe(&self) -> &Pipe {
+        &self.0
+    }
+}
+
+impl File for PipeReader {
+    fn read(&mut self) -> Result<Option<u8>, FileError> {
+        let mut buf = [0];
+        let len = self.0.read_bytes(&mut buf)?;
+        Ok(if len == 0 { None } else { Some(buf[0]) })
+    }
+
+    fn write(&mut self, _: u8) -> Result<(), FileError> {
+        Err(FileError::WritingNotAvailable)
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, FileError> {
+        self.0.read_bytes(buf)
+    }
+
+    fn read_value(&mut self, buf: &mut [u8]) -> Result<usize, FileError> {
+        self.0.read_value(buf)
+    }
+
+    fn is_ready(&self) -> bool {
+        self.0.is_readable()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.0.lock().readers -= 1;
+        self.0.shared.changed.notify_all();
+    }
+}
+
+/// Writer end of a pipe. The writer is closed when it's dropped.
+#[derive(Debug)]
+pub struct PipeWriter(Pipe);
+
+impl PipeWriter {
+    pub fn pipe(&self) -> &Pipe {
+        &self.0
+    }
+}
+
+impl File for PipeWriter {
+    fn read(&mut self) -> Result<Option<u8>, FileError> {
+        Err(FileError::ReadingNotAvailable)
+    }
+
+    fn write(&mut self, val: u8) -> Result<(), FileError> {
+        self.0.write_bytes(&[val])?;
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, buf: &[u8]) -> Result<usize, FileError> {
+        self.0.write_bytes(buf)
+    }
+
+    fn write_value(&mut self, buf: &[u8]) -> Result<usize, FileError> {
+        self.0.write_value(buf)
+    }
+
+    fn is_ready(&self) -> bool {
+        self.0.is_writable()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.0.lock().writers -= 1;
+        self.0.shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipe_read_write() {
+        assert_eq!(Pipe::new(0).capacity(), 1);
+
+        let (mut writer, mut reader) = pipe(2);
+        let pipe = reader.pipe().clone();
+
+        assert_eq!(reader.read(), Err(FileError::WouldBlock));
+        assert_eq!(writer.write_bytes(&[1, 2, 3]), Ok(2));
+        assert_eq!(writer.write(3), Err(FileError::WouldBlock));
+        assert_eq!(pipe.len(), 2);
+
+        assert_eq!(reader.read(), Ok(Some(1)));
+        writer.write(3).unwrap();
+        drop(writer);
+
+        let mut buf = [0; 4];
+        assert_eq!(reader.read_bytes(&mut buf), Ok(2));
+        assert_eq!(buf[..2], [2, 3]);
+        assert_eq!(reader.read(), Ok(None));
+        pipe.wait_readable();
+
+        let mut writer = pipe.writer();
+        drop(reader);
+        assert_eq!(
+            writer.write(4),
+            Err(FileError::Io(io::ErrorKind::BrokenPipe))
+        );
+    }
+
+    #[test]
+    fn pipe_values() {
+        let (mut writer, mut reader) = pipe(4);
+        let mut buf = [0; 4];
+
+        writer.write(1).unwrap();
+        assert_eq!(reader.read_value(&mut buf), Err(FileError::WouldBlock));
+        assert_eq!(
+            writer.write_value(&[2, 3, 4, 5]),
+            Err(FileError::WouldBlock)
+        );
+        writer.write_value(&[2, 3, 4]).unwrap();
+        assert_eq!(reader.read_value(&mut buf), Ok(4));
+        assert_eq!(buf, [1, 2, 3, 4]);
+
+        // The value larger than the capacity is written to the empty pipe
+        assert_eq!(writer.write_value(&[1, 2, 3, 4, 5]), Ok(5));
+        assert_eq!(reader.read_value(&mut buf), Ok(4));
+        drop(writer);
+        assert_eq!(reader.read_value(&mut buf), Ok(1));
+        assert_eq!(buf[0], 5);
+    }
+}
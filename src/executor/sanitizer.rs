@@ -0,0 +1,419 @@
+use super::{
+    allocator::Allocator,
+    executor::Location,
+    memory::{Memory, MemoryError, MemoryPage},
+    snapshot::{Reader, SnapshotError, Writer},
+};
+use crate::common::UWord;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Heap allocation known to the sanitizer.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Allocation {
+    pub ptr: UWord,
+    pub size: UWord,
+    /// Location of the operation that allocated the memory.
+    pub allocated: Option<Location>,
+    /// Location of the operation that freed the memory.
+    pub freed: Option<Location>,
+}
+
+/// Invalid access of `size` bytes at `ptr` and the allocation it refers to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct HeapAccess {
+    pub ptr: UWord,
+    pub size: UWord,
+    pub allocation: Option<Allocation>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// Offset of the allocator block, including red zones.
+    block: UWord,
+    allocation: Allocation,
+}
+
+/// Checks heap accesses against live allocations.
+///
+/// Every allocation is surrounded by red zones, so overflows hit bytes that don't
+/// belong to any allocation. Freed allocations are kept in a quarantine until it
+/// grows over the limit, so dangling pointers don't hit reused memory.
+#[derive(Debug)]
+pub(super) struct Sanitizer {
+    red_zone: UWord,
+    quarantine_limit: UWord,
+    live: BTreeMap<UWord, Entry>,
+    quarantine: VecDeque<Entry>,
+    quarantined: UWord,
+}
+
+impl Sanitizer {
+    /// Creates a sanitizer which knows the blocks already used by the `allocator`.
+    pub fn new(red_zone: UWord, quarantine_limit: UWord, allocator: &Allocator) -> Self {
+        let live = allocator
+            .used()
+            .map(|(block, size)| {
+                let ptr = block + Memory::HEAP_BASE;
+                let allocation = Allocation {
+                    ptr,
+                    size,
+                    allocated: None,
+                    freed: None,
+                };
+
+                (ptr, Entry { block, allocation })
+            })
+            .collect();
+
+        Self {
+            red_zone,
+            quarantine_limit,
+            live,
+            quarantine: VecDeque::new(),
+            quarantined: 0,
+        }
+    }
+
+    pub fn alloc(
+        &mut self,
+        allocator: &mut Allocator,
+        page: &mut MemoryPage,
+        size: UWord,
+        site: Option<Location>,
+    ) -> Result<UWord, MemoryError> {
+        let total = self
+            .red_zone
+            .checked_mul(2)
+            .and_then(|zones| zones.checked_add(size))
+            .ok_or(MemoryError::PageOverflow(page.name()))?;
+
+        let block = allocator.alloc(page, total)?;
+        let ptr = block + self.red_zone + Memory::HEAP_BASE;
+        let allocation = Allocation {
+            ptr,
+            size,
+            allocated: site,
+            freed: None,
+        };
+
+        self.live.insert(ptr, Entry { block, allocation });
+        Ok(ptr)
+    }
+
+    pub fn free(
+        &mut self,
+        allocator: &mut Allocator,
+        page: &mut MemoryPage,
+        ptr: UWord,
+        site: Option<Location>,
+    ) -> Result<(), MemoryError> {
+        let mut entry = match self.live.remove(&ptr) {
+            Some(entry) => entry,
+            None if self.quarantine.iter().any(|e| e.allocation.ptr == ptr) => {
+                return Err(MemoryError::DoubleFree(ptr))
+            }
+            None => return Err(MemoryError::InvalidFree(ptr)),
+        };
+
+        entry.allocation.freed = site;
+        self.quarantined = self.quarantined.saturating_add(entry.allocation.size);
+        self.quarantine.push_back(entry);
+
+        while self.quarantined > self.quarantine_limit {
+            let entry = match self.quarantine.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+
+            self.quarantined -= entry.allocation.size;
+            allocator.free(page, entry.block)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `size` bytes at `ptr` are inside one live allocation.
+    pub fn check(&self, ptr: UWord, size: UWord) -> Result<(), MemoryError> {
+        if size == 0 {
+            return Ok(());
+        }
+
+        let end = ptr.saturating_add(size);
+        let prev = self
+            .live
+            .range(..=ptr)
+            .next_back()
+            .map(|(_, e)| e.allocation);
+
+        if prev.is_some_and(|a| end <= a.ptr.saturating_add(a.size)) {
+            return Ok(());
+        }
+
+        let access = |allocation| HeapAccess {
+            ptr,
+            size,
+            allocation,
+        };
+
+        let freed = self
+            .quarantine
+            .iter()
+            .map(|e| e.allocation)
+            .find(|a| ptr < a.ptr.saturating_add(a.size) && a.ptr < end);
+
+        if let Some(allocation) = freed {
+            return Err(MemoryError::UseAfterFree(access(Some(allocation))));
+        }
+
+        // The access is reported against the allocation which red zone it hits
+        let next = self.live.range(ptr..).next().map(|(_, e)| e.allocation);
+        let red_zone = self.red_zone;
+        let allocation = prev
+            .filter(|a| ptr < a.ptr.saturating_add(a.size).saturating_add(red_zone))
+            .or_else(|| next.filter(|a| a.ptr.saturating_sub(red_zone) < end))
+            .or(prev)
+            .or(next);
+
+        Err(MemoryError::HeapOverflow(access(allocation)))
+    }
+
+    pub fn save(&self, w: &mut Writer) {
+        w.word(self.red_zone);
+        w.word(self.quarantine_limit);
+
+        for entries in [
+            self.live.values().collect::<Vec<_>>(),
+            self.quarantine.iter().collect(),
+        ] {
+            w.word(entries.len() as UWord);
+
+            for entry in entries {
+                w.word(entry.block);
+                w.word(entry.allocation.ptr);
+                w.word(entry.allocation.size);
+                save_location(w, entry.allocation.allocated);
+                save_location(w, entry.allocation.freed);
+            }
+        }
+    }
+
+    /// Loads the sanitizer of the `allocator`. Every used block of the allocator
+    /// must hold exactly one allocation.
+    pub fn load(r: &mut Reader, allocator: &Allocator) -> Result<Self, SnapshotError> {
+        let mut blocks: BTreeMap<_, _> = allocator.used().collect();
+
+        let mut sanitizer = Self {
+            red_zone: r.word()?,
+            quarantine_limit: r.word()?,
+            live: BTreeMap::new(),
+            quarantine: VecDeque::new(),
+            quarantined: 0,
+        };
+
+        for quarantined in [false, true] {
+            for _ in 0..r.word()? {
+                let entry = Entry {
+                    block: r.word()?,
+                    allocation: Allocation {
+                        ptr: r.word()?,
+                        size: r.word()?,
+                        allocated: load_location(r)?,
+                        freed: load_location(r)?,
+                    },
+                };
+
+                let size = blocks
+                    .remove(&entry.block)
+                    .ok_or(SnapshotError::InvalidData)?;
+
+                let offset = entry
+                    .allocation
+                    .ptr
+                    .checked_sub(Memory::HEAP_BASE)
+                    .and_then(|ptr| ptr.checked_sub(entry.block))
+                    .ok_or(SnapshotError::InvalidData)?;
+
+                match offset.checked_add(entry.allocation.size) {
+                    Some(end) if end <= size => (),
+                    _ => return Err(SnapshotError::InvalidData),
+                }
+
+                if quarantined {
+                    sanitizer.quarantined =
+                        sanitizer.quarantined.saturating_add(entry.allocation.size);
+                    sanitizer.quarantine.push_back(entry);
+                } else if sanitizer.live.insert(entry.allocation.ptr, entry).is_some() {
+                    return Err(SnapshotError::InvalidData);
+                }
+            }
+        }
+
+        if !blocks.is_empty() {
+            return Err(SnapshotError::InvalidData);
+        }
+
+        Ok(sanitizer)
+    }
+}
+
+fn save_location(w: &mut Writer, location: Option<Location>) {
+    w.bool(location.is_some());
+
+    if let Some(location) = location {
+        w.word(location.function_id);
+        w.word(location.program_counter);
+    }
+}
+
+fn load_location(r: &mut Reader) -> Result<Option<Location>, SnapshotError> {
+    Ok(if r.bool()? {
+        Some(Location {
+            function_id: r.word()?,
+            program_counter: r.word()?,
+        })
+    } else {
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizer_check() {
+        let mut mem = Memory::from_limits(2048, 2048);
+        mem.enable_sanitizer(8, 8);
+
+        let site = |program_counter| {
+            Some(Location {
+                function_id: 1,
+                program_counter,
+            })
+        };
+
+        let a = mem.alloc_at(4, site(0)).unwrap();
+        let b = mem.alloc_at(8, site(1)).unwrap();
+        assert_eq!(a, Memory::HEAP_BASE + 8);
+
+        mem.set(a, 1_u32).unwrap();
+        mem.set_zeros(b, 8).unwrap();
+        assert_eq!(mem.get::<u32>(a), Ok(1));
+
+        let allocation = Allocation {
+            ptr: a,
+            size: 4,
+            allocated: site(0),
+            freed: None,
+        };
+
+        assert_eq!(
+            mem.get::<u32>(a + 2),
+            Err(MemoryError::HeapOverflow(HeapAccess {
+                ptr: a + 2,
+                size: 4,
+                allocation: Some(allocation),
+            }))
+        );
+        assert_eq!(
+            mem.set(a - 1, 0_u8),
+            Err(MemoryError::HeapOverflow(HeapAccess {
+                ptr: a - 1,
+                size: 1,
+                allocation: Some(allocation),
+            }))
+        );
+
+        let overflow = mem.copy(b - 4, a, 8).unwrap_err();
+        assert!(
+            matches!(overflow, MemoryError::HeapOverflow(access) if access.allocation.unwrap().ptr == b)
+        );
+
+        mem.free_at(a, site(2)).unwrap();
+        assert_eq!(mem.free(a), Err(MemoryError::DoubleFree(a)));
+        assert_eq!(
+            mem.get::<u8>(a),
+            Err(MemoryError::UseAfterFree(HeapAccess {
+                ptr: a,
+                size: 1,
+                allocation: Some(Allocation {
+                    freed: site(2),
+                    ..allocation
+                }),
+            }))
+        );
+
+        // The quarantine keeps up to 8 bytes, so `b` pushes `a` out
+        mem.free(b).unwrap();
+        assert!(matches!(
+            mem.get::<u8>(a),
+            Err(MemoryError::HeapOverflow(HeapAccess {
+                allocation: None,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            mem.get::<u8>(b),
+            Err(MemoryError::UseAfterFree(_))
+        ));
+    }
+
+    #[test]
+    fn sanitizer_load() {
+        let mut page = MemoryPage::new(2048, "heap");
+        let mut allocator = Allocator::new();
+        let mut sanitizer = Sanitizer::new(8, 8, &allocator);
+        let a = sanitizer.alloc(&mut allocator, &mut page, 4, None).unwrap();
+        let b = sanitizer.alloc(&mut allocator, &mut page, 4, None).unwrap();
+        sanitizer.free(&mut allocator, &mut page, b, None).unwrap();
+
+        let mut w = Writer::new();
+        sanitizer.save(&mut w);
+        let buf = w.finish();
+
+        let loaded = Sanitizer::load(&mut Reader::new(&buf).unwrap(), &allocator).unwrap();
+        assert_eq!(loaded.check(a, 4), Ok(()));
+        assert!(matches!(
+            loaded.check(b, 1),
+            Err(MemoryError::UseAfterFree(_))
+        ));
+
+        // The allocations must match used blocks of the allocator
+        assert_eq!(
+            Sanitizer::load(&mut Reader::new(&buf).unwrap(), &Allocator::new()).err(),
+            Some(SnapshotError::InvalidData)
+        );
+
+        let load = |block, ptr, size| {
+            let mut w = Writer::new();
+            w.word(8);
+            w.word(8);
+            w.word(1);
+            w.word(block);
+            w.word(ptr);
+            w.word(size);
+            w.bool(false);
+            w.bool(false);
+            w.word(0);
+
+            let mut allocator = Allocator::new();
+            allocator
+                .alloc(&mut MemoryPage::new(2048, "heap"), 24)
+                .unwrap();
+
+            let buf = w.finish();
+            Sanitizer::load(&mut Reader::new(&buf).unwrap(), &allocator).map(|_| ())
+        };
+
+        assert_eq!(load(0, Memory::HEAP_BASE + 8, 8), Ok(()));
+        assert_eq!(
+            load(0, Memory::HEAP_BASE + 8, 64),
+            Err(SnapshotError::InvalidData)
+        );
+        assert_eq!(load(0, 4, 4), Err(SnapshotError::InvalidData));
+        assert_eq!(
+            load(8, Memory::HEAP_BASE + 8, 8),
+            Err(SnapshotError::InvalidData)
+        );
+    }
+}
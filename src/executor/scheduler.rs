@@ -0,0 +1,399 @@
+use super::executor::{Executor, StopReason, Stopped};
+use crate::common::UWord;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Time source of a scheduler. The time is measured in ticks,
+/// `Slp` operations sleep for the number of ticks of their operand.
+pub trait Clock {
+    fn now(&self) -> u64;
+
+    /// Blocks until the time comes.
+    fn wait_until(&mut self, time: u64);
+}
+
+/// Clock which time moves only when the scheduler waits, so runs are deterministic.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct VirtualClock {
+    now: u64,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> u64 {
+        self.now
+    }
+
+    fn wait_until(&mut self, time: u64) {
+        self.now = self.now.max(time);
+    }
+}
+
+/// Clock that follows the system time.
+#[derive(Copy, Clone, Debug)]
+pub struct RealClock {
+    start: Instant,
+    tick: Duration,
+}
+
+impl RealClock {
+    /// Creates a clock with the duration of one tick.
+    pub fn new(tick: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            tick,
+        }
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> u64 {
+        (self.start.elapsed().as_nanos() / self.tick.as_nanos().max(1)) as u64
+    }
+
+    fn wait_until(&mut self, time: u64) {
+        let ticks = time.saturating_sub(self.now());
+        let nanos = self.tick.as_nanos().saturating_mul(ticks as u128);
+        std::thread::sleep(Duration::from_nanos(nanos.min(u64::MAX as u128) as u64));
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TaskState {
+    Ready,
+    Sleeping(u64),
+    /// Waits until the current file of the executor is ready, see `File::is_ready`.
+    Blocked(UWord),
+    Stopped(Stopped),
+}
+
+#[derive(Debug)]
+struct Task {
+    executor: Executor,
+    state: TaskState,
+}
+
+/// Runs many executors in turns.
+///
+/// Every turn runs the next ready executor for a number of operations. Sleeping
+/// executors are parked until their wake time. Executors blocked on a file are
+/// parked until the file is ready or the host wakes them. Executors that end, fail
+/// or stop on a breakpoint are stopped and not scheduled until removed.
+/// Executors of the same program should share it, see `Executor::from_shared`.
+#[derive(Debug)]
+pub struct Scheduler<C = VirtualClock> {
+    clock: C,
+    quantum: usize,
+    tasks: Vec<Option<Task>>,
+    ready: VecDeque<usize>,
+    sleeping: BinaryHeap<Reverse<(u64, usize)>>,
+}
+
+impl Scheduler {
+    pub const DEFAULT_QUANTUM: usize = 1024;
+
+    pub fn new() -> Self {
+        Self::with_clock(VirtualClock::new())
+    }
+}
+
+impl<C> Scheduler<C>
+where
+    C: Clock,
+{
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            clock,
+            quantum: Scheduler::DEFAULT_QUANTUM,
+            tasks: Vec::new(),
+            ready: VecDeque::new(),
+            sleeping: BinaryHeap::new(),
+        }
+    }
+
+    /// Sets the number of operations an executor runs in one turn.
+    pub fn set_quantum(&mut self, quantum: usize) {
+        self.quantum = quantum.max(1);
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    /// Adds a ready executor and returns its id.
+    pub fn add(&mut self, executor: Executor) -> usize {
+        let id = self.tasks.len();
+        self.tasks.push(Some(Task {
+            executor,
+            state: TaskState::Ready,
+        }));
+
+        self.ready.push_back(id);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Executor> {
+        let task = self.tasks.get_mut(id)?.take()?;
+        self.ready.retain(|&r| r != id);
+        Some(task.executor)
+    }
+
+    pub fn executor(&self, id: usize) -> Option<&Executor> {
+        self.task(id).map(|task| &task.executor)
+    }
+
+    pub fn executor_mut(&mut self, id: usize) -> Option<&mut Executor> {
+        self.tasks
+            .get_mut(id)
+            .and_then(Option::as_mut)
+            .map(|task| &mut task.executor)
+    }
+
+    pub fn state(&self, id: usize) -> Option<TaskState> {
+        self.task(id).map(|task| task.state)
+    }
+
+    fn task(&self, id: usize) -> Option<&Task> {
+        self.tasks.get(id).and_then(Option::as_ref)
+    }
+
+    /// Makes the sleeping or blocked executor ready.
+    pub fn wake(&mut self, id: usize) -> bool {
+        match self.tasks.get_mut(id).and_then(Option::as_mut) {
+            Some(task) if matches!(task.state, TaskState::Sleeping(_) | TaskState::Blocked(_)) => {
+                task.state = TaskState::Ready;
+                self.ready.push_back(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Wakes executors which wake time has come.
+    fn wake_due(&mut self) {
+        let now = self.clock.now();
+
+        while let Some(&Reverse((time, id))) = self.sleeping.peek() {
+            if time > now {
+                break;
+            }
+
+            self.sleeping.pop();
+
+            if let Some(task) = self.tasks.get_mut(id).and_then(Option::as_mut) {
+                if task.state == TaskState::Sleeping(time) {
+                    task.state = TaskState::Ready;
+                    self.ready.push_back(id);
+                }
+            }
+        }
+    }
+
+    /// Wakes executors which files are ready.
+    fn wake_ready(&mut self) {
+        for (id, task) in self.tasks.iter_mut().enumerate() {
+            let Some(task) = task else { continue };
+
+            if let TaskState::Blocked(fd) = task.state {
+                if task.executor.files().is_ready(fd) {
+                    task.state = TaskState::Ready;
+                    self.ready.push_back(id);
+                }
+            }
+        }
+    }
+
+    /// Returns the earliest wake time.
+    fn next_wake(&mut self) -> Option<u64> {
+        while let Some(&Reverse((time, id))) = self.sleeping.peek() {
+            // The entry is stale if the executor was woken or removed before
+            match self.state(id) {
+                Some(TaskState::Sleeping(t)) if t == time => return Some(time),
+                _ => self.sleeping.pop(),
+            };
+        }
+
+        None
+    }
+
+    /// Runs one turn of the next ready executor and returns its id and the result
+    /// of the run. If no executor is ready, waits for the earliest sleeping one.
+    ///
+    /// Returns `None` if there are no ready or sleeping executors. Blocked executors
+    /// are woken when their files are ready, but they are not waited for.
+    pub fn step(&mut self) -> Option<(usize, Stopped)> {
+        self.wake_due();
+        self.wake_ready();
+
+        if self.ready.is_empty() {
+            let time = self.next_wake()?;
+            self.clock.wait_until(time);
+            return self.step();
+        }
+
+        let id = self.ready.pop_front()?;
+        let task = self.tasks.get_mut(id)?.as_mut()?;
+        let stopped = task.executor.run(self.quantum);
+
+        task.state = match stopped {
+            Ok(StopReason::BudgetExhausted) => {
+                self.ready.push_back(id);
+                TaskState::Ready
+            }
+            Ok(StopReason::Sleep(ticks)) => {
+                let time = self.clock.now().saturating_add(ticks as u64);
+                self.sleeping.push(Reverse((time, id)));
+                TaskState::Sleeping(time)
+            }
+            Ok(StopReason::Blocked(fd)) => TaskState::Blocked(fd),
+            stopped => TaskState::Stopped(stopped),
+        };
+
+        Some((id, stopped))
+    }
+
+    /// Runs executors until none of them is ready or sleeping.
+    pub fn run(&mut self) {
+        while self.step().is_some() {}
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{BinOp, Op, Operand, UnOp},
+        executor::{pipe, ExecutionError, File, MemoryError, Program},
+    };
+
+    fn executor(ops: &[Op]) -> Executor {
+        let mut program = Program::new();
+        program.push(8, ops.to_vec());
+
+        let mut exe = Executor::from_shared(&program);
+        exe.call(0, 0).unwrap();
+        exe
+    }
+
+    #[test]
+    fn scheduler_step() {
+        let mut scheduler = Scheduler::new();
+        scheduler.set_quantum(2);
+
+        let a = scheduler.add(executor(&[
+            Op::Slp(Operand::Val(5)),
+            Op::End(Operand::Val(1)),
+        ]));
+        let b = scheduler.add(executor(&[
+            Op::Nop,
+            Op::Nop,
+            Op::Slp(Operand::Val(2)),
+            Op::End(Operand::Val(2)),
+        ]));
+        let c = scheduler.add(executor(&[Op::Nop, Op::Fre(Operand::Val(0))]));
+
+        assert_eq!(scheduler.step(), Some((a, Ok(StopReason::Sleep(5)))));
+        assert_eq!(scheduler.state(a), Some(TaskState::Sleeping(5)));
+        assert_eq!(scheduler.step(), Some((b, Ok(StopReason::BudgetExhausted))));
+        assert_eq!(
+            scheduler.step(),
+            Some((
+                c,
+                Err(ExecutionError::MemoryError(MemoryError::InvalidFree(0)))
+            ))
+        );
+        assert_eq!(scheduler.step(), Some((b, Ok(StopReason::Sleep(2)))));
+
+        assert_eq!(scheduler.step(), Some((b, Ok(StopReason::End(2)))));
+        assert_eq!(scheduler.now(), 2);
+        assert_eq!(scheduler.step(), Some((a, Ok(StopReason::End(1)))));
+        assert_eq!(scheduler.now(), 5);
+        assert_eq!(scheduler.step(), None);
+
+        assert_eq!(
+            scheduler.state(a),
+            Some(TaskState::Stopped(Ok(StopReason::End(1))))
+        );
+        assert!(scheduler.remove(a).is_some());
+        assert_eq!(scheduler.state(a), None);
+    }
+
+    #[test]
+    fn scheduler_wake() {
+        let mut scheduler = Scheduler::new();
+        let a = scheduler.add(executor(&[
+            Op::Slp(Operand::Val(100)),
+            Op::End(Operand::Val(1)),
+        ]));
+
+        assert_eq!(scheduler.step(), Some((a, Ok(StopReason::Sleep(100)))));
+        assert!(scheduler.wake(a));
+        assert!(!scheduler.wake(a));
+
+        scheduler.run();
+        assert_eq!(scheduler.now(), 0);
+        assert_eq!(
+            scheduler.state(a),
+            Some(TaskState::Stopped(Ok(StopReason::End(1))))
+        );
+    }
+
+    #[test]
+    fn scheduler_pipe() {
+        fn executor<F: File + 'static>(ops: &[Op], file: F) -> Executor {
+            let mut program = Program::new();
+            program.push(16, ops.to_vec());
+
+            let mut exe = Executor::from_shared(&program);
+            exe.files_mut().open(file).unwrap();
+            exe.files_mut().set_current(0).unwrap();
+            exe.call(0, 0).unwrap();
+            exe
+        }
+
+        let (writer, reader) = pipe(1);
+        let mut scheduler = Scheduler::new();
+        let a = scheduler.add(executor(
+            &[
+                Op::In(BinOp::new(Operand::Loc(0), Operand::Loc(8))),
+                Op::In(BinOp::new(Operand::Loc(1), Operand::Loc(8))),
+                Op::End(Operand::Loc(0)),
+            ],
+            reader,
+        ));
+        let b = scheduler.add(executor(
+            &[
+                Op::Out(UnOp::new(Operand::Val(4))),
+                Op::Out(UnOp::new(Operand::Val(1))),
+                Op::End(Operand::Val(0)),
+            ],
+            writer,
+        ));
+
+        assert_eq!(scheduler.step(), Some((a, Ok(StopReason::Blocked(0)))));
+        assert_eq!(scheduler.state(a), Some(TaskState::Blocked(0)));
+        assert_eq!(scheduler.step(), Some((b, Ok(StopReason::Blocked(0)))));
+        assert_eq!(scheduler.step(), Some((a, Ok(StopReason::Blocked(0)))));
+        assert_eq!(scheduler.step(), Some((b, Ok(StopReason::End(0)))));
+        assert_eq!(scheduler.step(), Some((a, Ok(StopReason::End(0x0104)))));
+        assert_eq!(scheduler.step(), None);
+    }
+}
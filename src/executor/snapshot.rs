@@ -0,0 +1,176 @@
+use super::{files::FilesError, memory::MemoryError};
+use crate::common::UWord;
+
+const MAGIC: &[u8; 4] = b"NISN";
+const VERSION: u8 = 1;
+const WORD_SIZE: u8 = std::mem::size_of::<UWord>() as u8;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SnapshotError {
+    UnexpectedEnd,
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    WordSizeMismatch(u8),
+    InvalidData,
+    MemoryError(MemoryError),
+    FilesError(FilesError),
+    UnknownFunction(UWord),
+    FileNotRestored(UWord),
+    /// The snapshot has more contexts than the limit of the executor allows.
+    ContextLimitExceeded,
+}
+
+impl From<MemoryError> for SnapshotError {
+    fn from(e: MemoryError) -> Self {
+        SnapshotError::MemoryError(e)
+    }
+}
+
+impl From<FilesError> for SnapshotError {
+    fn from(e: FilesError) -> Self {
+        SnapshotError::FilesError(e)
+    }
+}
+
+/// Writer of the snapshot binary format.
+///
+/// All numbers are little-endian words of the executor, except 64-bit counters.
+pub(super) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        buf.push(WORD_SIZE);
+
+        Self { buf }
+    }
+
+    pub fn word(&mut self, val: UWord) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, val: u64) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.buf.push(val as u8);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.word(bytes.len() as UWord);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub(super) struct Reader<'b> {
+    buf: &'b [u8],
+}
+
+impl<'b> Reader<'b> {
+    pub fn new(buf: &'b [u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Self { buf };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::InvalidHeader);
+        }
+
+        match *reader.take(2)? {
+            [VERSION, WORD_SIZE] => Ok(reader),
+            [VERSION, word_size] => Err(SnapshotError::WordSizeMismatch(word_size)),
+            [version, _] => Err(SnapshotError::UnsupportedVersion(version)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'b [u8], SnapshotError> {
+        if self.buf.len() < size {
+            return Err(SnapshotError::UnexpectedEnd);
+        }
+
+        let (bytes, rest) = self.buf.split_at(size);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    pub fn word(&mut self) -> Result<UWord, SnapshotError> {
+        let mut bytes = [0; WORD_SIZE as usize];
+        bytes.copy_from_slice(self.take(WORD_SIZE as usize)?);
+        Ok(UWord::from_le_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.take(1)? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(SnapshotError::InvalidData),
+        }
+    }
+
+    pub fn bytes(&mut self) -> Result<&'b [u8], SnapshotError> {
+        let len = self.word()?;
+        self.take(len as usize)
+    }
+
+    pub fn finish(self) -> Result<(), SnapshotError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(SnapshotError::InvalidData)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_read_write() {
+        let mut w = Writer::new();
+        w.word(12);
+        w.u64(u64::MAX);
+        w.bool(true);
+        w.bytes(b"abc");
+        let buf = w.finish();
+
+        let mut r = Reader::new(&buf).unwrap();
+        assert_eq!(r.word(), Ok(12));
+        assert_eq!(r.u64(), Ok(u64::MAX));
+        assert_eq!(r.bool(), Ok(true));
+        assert_eq!(r.bytes(), Ok(&b"abc"[..]));
+        assert_eq!(r.word(), Err(SnapshotError::UnexpectedEnd));
+        assert_eq!(r.finish(), Ok(()));
+    }
+
+    #[test]
+    fn snapshot_header() {
+        assert_eq!(
+            Reader::new(b"NISX\x01\x04").err(),
+            Some(SnapshotError::InvalidHeader)
+        );
+        assert_eq!(
+            Reader::new(b"NISN\x02\x04").err(),
+            Some(SnapshotError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            Reader::new(b"NISN\x01\x03").err(),
+            Some(SnapshotError::WordSizeMismatch(3))
+        );
+        assert_eq!(Reader::new(b"NI").err(), Some(SnapshotError::UnexpectedEnd));
+    }
+}